@@ -0,0 +1,106 @@
+use std::fmt;
+
+/// Why a BUPT-portal operation failed.
+///
+/// Rejections keep the message rendered by the portal, so it can still be
+/// shown verbatim when there is no localized text for the code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuptError {
+    WrongCredentials(String),
+    AccountSuspended(String),
+    TooManyDevices(String),
+    /// Rejected for a reason we don't recognize.
+    Rejected(String),
+    PortalUnreachable(String),
+    UnexpectedStatus(u16),
+    /// A redirect status without a `Location` header.
+    MissingLocation(u16),
+    /// The login form answered with a redirect, to the given location.
+    UnexpectedRedirect(String),
+    MissingCookie,
+    /// The probes still pass after logging out.
+    StillOnline,
+    /// The redirect chain, ending with the URL that was already visited.
+    RedirectLoop(Vec<String>),
+    TooManyRedirects(Vec<String>),
+}
+
+impl BuptError {
+    /// Classifies the message of the portal's `ui error message` div.
+    pub fn from_reason(reason: &str) -> Self {
+        let reason = reason.to_string();
+        if reason.contains("密码") || reason.contains("用户不存在") {
+            BuptError::WrongCredentials(reason)
+        } else if ["欠费", "停机", "停用", "冻结", "暂停"]
+            .iter()
+            .any(|keyword| reason.contains(keyword))
+        {
+            BuptError::AccountSuspended(reason)
+        } else if ["在线", "终端", "设备"]
+            .iter()
+            .any(|keyword| reason.contains(keyword))
+        {
+            BuptError::TooManyDevices(reason)
+        } else {
+            BuptError::Rejected(reason)
+        }
+    }
+
+    /// Stable identifier for the frontend.
+    pub fn code(&self) -> &'static str {
+        match self {
+            BuptError::WrongCredentials(_) => "wrong_credentials",
+            BuptError::AccountSuspended(_) => "account_suspended",
+            BuptError::TooManyDevices(_) => "too_many_devices",
+            BuptError::Rejected(_) => "rejected",
+            BuptError::PortalUnreachable(_) => "portal_unreachable",
+            BuptError::UnexpectedStatus(_) => "unexpected_status",
+            BuptError::MissingLocation(_) => "missing_location",
+            BuptError::UnexpectedRedirect(_) => "unexpected_redirect",
+            BuptError::MissingCookie => "missing_cookie",
+            BuptError::StillOnline => "still_online",
+            BuptError::RedirectLoop(_) => "redirect_loop",
+            BuptError::TooManyRedirects(_) => "too_many_redirects",
+        }
+    }
+
+    /// Whether retrying with the same account is pointless.
+    pub fn is_permanent(&self) -> bool {
+        matches!(
+            self,
+            BuptError::WrongCredentials(_) | BuptError::AccountSuspended(_)
+        )
+    }
+}
+
+impl fmt::Display for BuptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuptError::WrongCredentials(reason)
+            | BuptError::AccountSuspended(reason)
+            | BuptError::TooManyDevices(reason)
+            | BuptError::Rejected(reason) => write!(f, "BUPT-portal 认证失败: {}", reason),
+            BuptError::PortalUnreachable(e) => write!(f, "无法连接 BUPT-portal: {}", e),
+            BuptError::UnexpectedStatus(status) => write!(f, "unexpected status code: {}", status),
+            BuptError::MissingLocation(status) => {
+                write!(f, "redirect without Location header: {}", status)
+            }
+            BuptError::UnexpectedRedirect(location) => {
+                write!(f, "unexpected redirect: {}", location)
+            }
+            BuptError::MissingCookie => write!(f, "BUPT-portal 未返回 Cookie, 无法认证"),
+            BuptError::StillOnline => write!(f, "BUPT-portal 注销后仍然在线"),
+            BuptError::RedirectLoop(chain) => write!(f, "redirect loop: {}", chain.join(" -> ")),
+            BuptError::TooManyRedirects(chain) => {
+                write!(f, "too many redirects: {}", chain.join(" -> "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuptError {}
+
+/// Returns the [`BuptError`] behind `error`, if any.
+pub fn classify(error: &anyhow::Error) -> Option<&BuptError> {
+    error.downcast_ref::<BuptError>()
+}
@@ -0,0 +1,326 @@
+use anyhow::{bail, Result};
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    pin::pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+/// Upper bound for response bodies kept in memory, portal pages are small.
+pub const MAX_BODY_LEN: usize = 16 * 1024;
+/// Upper bound for a whole response read by [`StdHttpClient`], enough for the
+/// status line, the headers and a body of [`MAX_BODY_LEN`].
+pub const MAX_RESPONSE_LEN: usize = MAX_BODY_LEN + 4096;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+}
+
+impl Method {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The minimal HTTP transport the portal protocol needs.
+///
+/// Implementations must not follow redirects: the login flow inspects every
+/// `302` itself.
+pub trait HttpClient {
+    fn request(
+        &mut self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Response>;
+}
+
+/// [`HttpClient`] for async transports, so the portal flow can run on an
+/// executor next to other tasks. The same rules apply.
+pub trait AsyncHttpClient {
+    fn request(
+        &mut self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> impl Future<Output = Result<Response>>;
+}
+
+/// Runs a blocking [`HttpClient`] where an [`AsyncHttpClient`] is expected.
+/// Its requests are done by the time they are first polled.
+pub(crate) struct Blocking<'a, C>(pub &'a mut C);
+
+impl<C: HttpClient> AsyncHttpClient for Blocking<'_, C> {
+    fn request(
+        &mut self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> impl Future<Output = Result<Response>> {
+        std::future::ready(self.0.request(method, url, headers, body))
+    }
+}
+
+/// Runs the async portal flow over [`Blocking`] requests, which never make
+/// it wait, so a single poll completes it.
+pub(crate) fn block_on<T>(future: impl Future<Output = T>) -> T {
+    let mut context = Context::from_waker(Waker::noop());
+    match pin!(future).poll(&mut context) {
+        Poll::Ready(value) => value,
+        Poll::Pending => unreachable!("blocking requests are always ready"),
+    }
+}
+
+/// The plain HTTP/1.0 request [`StdHttpClient`] sends, for async clients to
+/// send the same. The server closes the connection after the response.
+pub fn encode_request(
+    method: Method,
+    host: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Vec<u8> {
+    let mut request = format!(
+        "{} {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n",
+        method.as_str(),
+        path,
+        host
+    );
+    for (key, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", key, value));
+    }
+    if method == Method::Post || !body.is_empty() {
+        request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    request.push_str("\r\n");
+    let mut request = request.into_bytes();
+    request.extend_from_slice(body);
+    request
+}
+
+/// Parses a response read until the server closed the connection. The body
+/// is cut at [`MAX_BODY_LEN`].
+pub fn parse_response(data: &[u8]) -> Result<Response> {
+    let (head, body) = [&b"\r\n\r\n"[..], b"\n\n"]
+        .iter()
+        .find_map(|separator| {
+            data.windows(separator.len())
+                .position(|window| window == *separator)
+                .map(|index| (&data[..index], &data[index + separator.len()..]))
+        })
+        // Closed before the end of the headers
+        .unwrap_or((data, &[]));
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head.lines();
+    let line = lines.next().unwrap_or_default();
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("invalid status line: {}", line.trim()))?;
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+    Ok(Response {
+        status,
+        headers,
+        body: body[..body.len().min(MAX_BODY_LEN)].to_vec(),
+    })
+}
+
+/// Plain-HTTP client over std sockets, usable on the host.
+///
+/// Hosts can be redirected to another address with [`StdHttpClient::resolve`],
+/// e.g. to point `10.3.8.216` at a local fake portal.
+pub struct StdHttpClient {
+    timeout: Option<Duration>,
+    resolve: HashMap<String, SocketAddr>,
+}
+
+impl StdHttpClient {
+    pub fn new() -> Self {
+        Self {
+            timeout: Some(Duration::from_secs(20)),
+            resolve: HashMap::new(),
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends requests for `host` (as written in the URL, port included if any)
+    /// to `addr` instead.
+    pub fn resolve(mut self, host: impl Into<String>, addr: SocketAddr) -> Self {
+        self.resolve.insert(host.into(), addr);
+        self
+    }
+
+    fn connect(&self, host: &str) -> Result<TcpStream> {
+        let addr = match self.resolve.get(host) {
+            Some(addr) => *addr,
+            None => {
+                let with_port = if host.contains(':') {
+                    host.to_string()
+                } else {
+                    format!("{}:80", host)
+                };
+                with_port
+                    .to_socket_addrs()?
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("failed to resolve {}", host))?
+            }
+        };
+        let stream = match self.timeout {
+            Some(timeout) => TcpStream::connect_timeout(&addr, timeout)?,
+            None => TcpStream::connect(addr)?,
+        };
+        stream.set_read_timeout(self.timeout)?;
+        stream.set_write_timeout(self.timeout)?;
+        Ok(stream)
+    }
+}
+
+impl Default for StdHttpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Splits `http://host[:port]/path?query` into its host and request target.
+pub fn split_url(url: &str) -> Result<(&str, &str)> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        bail!("unsupported url: {}", url);
+    };
+    Ok(match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    })
+}
+
+/// Whether `url` starts with a scheme (RFC 3986: a letter, then letters,
+/// digits, `+`, `-` or `.`, then `:`), i.e. is absolute.
+fn has_scheme(url: &str) -> bool {
+    let Some((scheme, _)) = url.split_once(':') else {
+        return false;
+    };
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// Resolves a `Location` header against the URL that returned it.
+pub fn resolve_url(base: &str, location: &str) -> Result<String> {
+    if has_scheme(location) {
+        return Ok(location.to_string());
+    }
+    if let Some(rest) = location.strip_prefix("//") {
+        return Ok(format!("http://{}", rest));
+    }
+    let (host, path) = split_url(base)?;
+    if location.starts_with('/') {
+        return Ok(format!("http://{}{}", host, location));
+    }
+    let path = path.split(['?', '#']).next().unwrap_or("/");
+    let dir = &path[..path.rfind('/').map_or(0, |index| index + 1)];
+    Ok(format!("http://{}{}{}", host, dir, location))
+}
+
+impl HttpClient for StdHttpClient {
+    fn request(
+        &mut self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Response> {
+        let (host, path) = split_url(url)?;
+        let mut stream = self.connect(host)?;
+        stream.write_all(&encode_request(method, host, path, headers, body))?;
+
+        let mut data = Vec::new();
+        stream
+            .take(MAX_RESPONSE_LEN as u64)
+            .read_to_end(&mut data)?;
+        parse_response(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "http://10.3.8.216/a/index?x=1";
+
+    #[test]
+    fn absolute_locations_are_kept() {
+        assert_eq!(
+            resolve_url(BASE, "http://connect.rom.miui.com/generate_204").unwrap(),
+            "http://connect.rom.miui.com/generate_204"
+        );
+        assert_eq!(
+            resolve_url(BASE, "https://example.com").unwrap(),
+            "https://example.com"
+        );
+    }
+
+    #[test]
+    fn urls_in_the_query_stay_relative() {
+        assert_eq!(
+            resolve_url(BASE, "login?next=http://example.com/").unwrap(),
+            "http://10.3.8.216/a/login?next=http://example.com/"
+        );
+        assert_eq!(
+            resolve_url(BASE, "/index?url=http://connect.rom.miui.com").unwrap(),
+            "http://10.3.8.216/index?url=http://connect.rom.miui.com"
+        );
+    }
+
+    #[test]
+    fn relative_locations_resolve_against_the_base() {
+        assert_eq!(
+            resolve_url(BASE, "//captive.apple.com/").unwrap(),
+            "http://captive.apple.com/"
+        );
+        assert_eq!(
+            resolve_url(BASE, "login").unwrap(),
+            "http://10.3.8.216/a/login"
+        );
+        assert_eq!(
+            resolve_url("http://10.3.8.216", "index").unwrap(),
+            "http://10.3.8.216/index"
+        );
+    }
+}
@@ -0,0 +1,387 @@
+//! The BUPT campus portal protocol: connectivity probes, login, logout and
+//! the session page, over any [`HttpClient`].
+//!
+//! Kept free of ESP-IDF so it builds and tests on the host against
+//! `tools/portal-emulator`; the firmware plugs in its own client.
+//!
+//! The flow is written once, async over an [`AsyncHttpClient`]. The blocking
+//! functions run it with a blocking [`HttpClient`], whose requests complete
+//! without ever making it wait.
+
+mod error;
+mod http;
+mod probe;
+mod session;
+
+use anyhow::Result;
+use urlencoding::encode;
+
+pub use error::{classify, BuptError};
+use http::{block_on, Blocking};
+
+pub use http::{
+    encode_request, parse_response, resolve_url, split_url, AsyncHttpClient, HttpClient, Method,
+    Response, StdHttpClient, MAX_BODY_LEN, MAX_RESPONSE_LEN,
+};
+pub use probe::{
+    check_race, check_race_async, check_sequential, check_sequential_async, Expect, Probe,
+    ProbeConfig, Strategy,
+};
+pub use session::SessionStatus;
+
+fn fail<T>(error: BuptError) -> Result<T> {
+    log::error!("{}", error);
+    Err(error.into())
+}
+
+async fn request(
+    client: &mut impl AsyncHttpClient,
+    method: Method,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<Response> {
+    client
+        .request(method, url, headers, body)
+        .await
+        .map_err(|e| BuptError::PortalUnreachable(e.to_string()).into())
+}
+
+pub const CHECK_URL: &str = "http://connect.rom.miui.com/generate_204?cmd=redirect&arubalp=12345";
+const LOGIN_URL: &str = "http://10.3.8.216/login";
+const LOGOUT_URL: &str = "http://10.3.8.216/logout";
+const STATUS_URL: &str = "http://10.3.8.216/index";
+
+/// Redirect hops followed by a single probe before giving up.
+const MAX_REDIRECTS: usize = 8;
+
+#[derive(Debug)]
+pub enum BuptNetStatus {
+    Authenticated,
+    NotAuthenticated(Option<String>),
+}
+
+#[derive(Debug)]
+pub struct CheckResult {
+    pub status: BuptNetStatus,
+    /// Every URL requested, starting with the probe URL. Shows which gateway
+    /// intercepted the probe.
+    pub chain: Vec<String>,
+}
+
+/// Follows the redirects of `probe` until it gives a verdict.
+pub fn check_with(client: &mut impl HttpClient, probe: &Probe) -> Result<CheckResult> {
+    block_on(check_with_async(&mut Blocking(client), probe))
+}
+
+/// Async [`check_with`].
+pub async fn check_with_async(
+    client: &mut impl AsyncHttpClient,
+    probe: &Probe,
+) -> Result<CheckResult> {
+    let mut chain = vec![probe.url.clone()];
+    loop {
+        let url = chain.last().unwrap();
+        let first = chain.len() == 1;
+        // Pages behind a redirect are always fetched, whatever the probe method
+        let method = if first { probe.method } else { Method::Get };
+        log::debug!("checking bupt network status with url: {}", url);
+        let response = request(client, method, url, &[], &[]).await?;
+        log::debug!("response status: {}", response.status);
+        let status = match response.status {
+            // Logged in, not redirected
+            _ if first && probe.expect.matches(&response) => BuptNetStatus::Authenticated,
+            // Redirect to login page
+            301 | 302 | 303 | 307 | 308 => {
+                let Some(location) = response.header("Location") else {
+                    return fail(BuptError::MissingLocation(response.status));
+                };
+                let location = resolve_url(url, location)?;
+                log::info!("redirected to: {}", location);
+                if chain.contains(&location) {
+                    chain.push(location);
+                    return fail(BuptError::RedirectLoop(chain));
+                }
+                chain.push(location);
+                // The chain holds the probe URL and one URL per hop.
+                if chain.len() - 1 > MAX_REDIRECTS {
+                    return fail(BuptError::TooManyRedirects(chain));
+                }
+                continue;
+            }
+            // Intercepted, this is the login page
+            200 => BuptNetStatus::NotAuthenticated(
+                response
+                    .header("Set-Cookie")
+                    .and_then(|cookie| cookie.split(';').next().map(|cookie| cookie.to_string())),
+            ),
+            _ => return fail(BuptError::UnexpectedStatus(response.status)),
+        };
+        return Ok(CheckResult { status, chain });
+    }
+}
+
+/// Whether the portal rejected the login for lack of a valid session, which
+/// is what a missing cookie looks like.
+fn is_session_expired(reason: &str) -> bool {
+    reason.contains("会话")
+}
+
+/// The message of the `ui error message` div of a login page.
+fn failure_reason(body: &str) -> Option<&str> {
+    const START: &str = "<div class=\"ui error message\">";
+    let start = body.find(START)? + START.len();
+    let end = body[start..].find("</div>")?;
+    Some(body[start..start + end].trim())
+}
+
+async fn auth(
+    client: &mut impl AsyncHttpClient,
+    probes: &[Probe],
+    username: &str,
+    password: &str,
+    cookie: Option<String>,
+) -> Result<()> {
+    let mut headers = vec![("Content-Type", "application/x-www-form-urlencoded")];
+    if let Some(cookie) = &cookie {
+        headers.push(("Cookie", cookie));
+    }
+    let body = format!("user={}&pass={}", encode(username), encode(password));
+    let response = request(client, Method::Post, LOGIN_URL, &headers, body.as_bytes()).await?;
+    log::debug!("response status: {}", response.status);
+    match response.status {
+        302 => fail(BuptError::UnexpectedRedirect(
+            response.header("Location").unwrap_or_default().to_string(),
+        )),
+        200 => match check_sequential_async(client, probes).await?.status {
+            BuptNetStatus::Authenticated => {
+                log::info!("BUPT-portal authenticated successfully");
+                Ok(())
+            }
+            _ => {
+                let body = String::from_utf8_lossy(&response.body);
+                match failure_reason(&body) {
+                    // Any other reason stands, whatever the cookie.
+                    Some(reason) if !is_session_expired(reason) => {
+                        fail(BuptError::from_reason(reason))
+                    }
+                    _ if cookie.is_none() => fail(BuptError::MissingCookie),
+                    Some(reason) => fail(BuptError::from_reason(reason)),
+                    None => fail(BuptError::from_reason("Unknown error")),
+                }
+            }
+        },
+        _ => fail(BuptError::UnexpectedStatus(response.status)),
+    }
+}
+
+/// Logs in unless already online. The probes always run in order here, the
+/// cookie has to come from the same client that posts the login form.
+pub fn login_with(
+    client: &mut impl HttpClient,
+    probes: &[Probe],
+    username: &str,
+    password: &str,
+) -> Result<()> {
+    block_on(login_with_async(
+        &mut Blocking(client),
+        probes,
+        username,
+        password,
+    ))
+}
+
+/// Async [`login_with`].
+pub async fn login_with_async(
+    client: &mut impl AsyncHttpClient,
+    probes: &[Probe],
+    username: &str,
+    password: &str,
+) -> Result<()> {
+    log::info!("Checking BUPT-portal status...");
+    match check_sequential_async(client, probes)
+        .await
+        .map(|check| check.status)
+    {
+        Ok(BuptNetStatus::Authenticated) => {
+            log::info!("BUPT-portal is already authenticated");
+            Ok(())
+        }
+        Ok(BuptNetStatus::NotAuthenticated(cookie)) => {
+            log::info!(
+                "BUPT-portal not authenticated, authenticating with account: {}",
+                username,
+            );
+            match &cookie {
+                Some(cookie) => log::info!("Cookie: {}", cookie),
+                None => {
+                    log::warn!("No cookie found in response, may not be able to authenticate")
+                }
+            }
+            auth(client, probes, username, password, cookie).await
+        }
+        Err(e) => {
+            log::error!("BUPT-portal status check failed: {}", e);
+            Err(e)
+        }
+    }
+}
+
+/// Ends the portal session of this device, then checks it is really gone.
+///
+/// The portal binds a session to the IP of the device and its logout link
+/// takes no account, so there is no way to end the session of a given
+/// account. Whatever account is logged in from this device is logged out.
+pub fn logout_with(client: &mut impl HttpClient, probes: &[Probe]) -> Result<()> {
+    block_on(logout_with_async(&mut Blocking(client), probes))
+}
+
+/// Async [`logout_with`].
+pub async fn logout_with_async(client: &mut impl AsyncHttpClient, probes: &[Probe]) -> Result<()> {
+    log::info!("Logging out of BUPT-portal");
+    let response = request(client, Method::Get, LOGOUT_URL, &[], &[]).await?;
+    log::debug!("response status: {}", response.status);
+    match response.status {
+        200 | 302 => match check_sequential_async(client, probes).await?.status {
+            BuptNetStatus::NotAuthenticated(_) => {
+                log::info!("BUPT-portal logged out successfully");
+                Ok(())
+            }
+            BuptNetStatus::Authenticated => fail(BuptError::StillOnline),
+        },
+        _ => fail(BuptError::UnexpectedStatus(response.status)),
+    }
+}
+
+/// The session page, or [`SessionStatus::offline`] when not logged in.
+pub fn status_with(client: &mut impl HttpClient, probes: &[Probe]) -> Result<SessionStatus> {
+    block_on(status_with_async(&mut Blocking(client), probes))
+}
+
+/// Async [`status_with`].
+pub async fn status_with_async(
+    client: &mut impl AsyncHttpClient,
+    probes: &[Probe],
+) -> Result<SessionStatus> {
+    let check = check_sequential_async(client, probes).await?;
+    if let BuptNetStatus::NotAuthenticated(_) = check.status {
+        return Ok(SessionStatus::offline());
+    }
+    let response = request(client, Method::Get, STATUS_URL, &[], &[]).await?;
+    log::debug!("response status: {}", response.status);
+    match response.status {
+        200 => Ok(SessionStatus::parse(&String::from_utf8_lossy(
+            &response.body,
+        ))),
+        _ => fail(BuptError::UnexpectedStatus(response.status)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Answers every request with the next scripted response.
+    struct Scripted(VecDeque<Response>);
+
+    impl HttpClient for Scripted {
+        fn request(
+            &mut self,
+            _: Method,
+            _: &str,
+            _: &[(&str, &str)],
+            _: &[u8],
+        ) -> Result<Response> {
+            self.0
+                .pop_front()
+                .ok_or_else(|| anyhow::anyhow!("no more responses"))
+        }
+    }
+
+    fn response(status: u16, headers: &[(&str, &str)], body: &str) -> Response {
+        Response {
+            status,
+            headers: headers
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn login_page(error: &str) -> Response {
+        response(
+            200,
+            &[],
+            &format!("<div class=\"ui error message\">\n  {}\n</div>", error),
+        )
+    }
+
+    /// Redirects from `/0` to `/1` and so on, ending at the login page.
+    fn redirects(hops: usize) -> Scripted {
+        let mut responses: VecDeque<_> = (1..=hops)
+            .map(|hop| response(302, &[("Location", &format!("/{}", hop))], ""))
+            .collect();
+        responses.push_back(login_page(""));
+        Scripted(responses)
+    }
+
+    #[test]
+    fn follows_up_to_max_redirects() {
+        let probe = Probe::generate_204("http://10.3.8.216/0");
+        let result = check_with(&mut redirects(MAX_REDIRECTS), &probe).unwrap();
+        assert_eq!(result.chain.len(), MAX_REDIRECTS + 1);
+
+        let error = check_with(&mut redirects(MAX_REDIRECTS + 1), &probe).unwrap_err();
+        assert!(matches!(
+            classify(&error),
+            Some(BuptError::TooManyRedirects(_))
+        ));
+    }
+
+    #[test]
+    fn wrong_password_without_cookie_is_permanent() {
+        let probes = [Probe::generate_204(CHECK_URL)];
+        let mut client = Scripted(VecDeque::from([
+            // Check, login page without a cookie
+            login_page(""),
+            // Login, rejected
+            login_page("账号或密码错误"),
+            // Check again, still on the login page
+            login_page(""),
+        ]));
+        let error = login_with(&mut client, &probes, "user", "pass").unwrap_err();
+        assert!(matches!(
+            classify(&error),
+            Some(BuptError::WrongCredentials(_))
+        ));
+    }
+
+    #[test]
+    fn login_redirect_is_typed() {
+        let probes = [Probe::generate_204(CHECK_URL)];
+        let mut client = Scripted(VecDeque::from([
+            login_page(""),
+            response(302, &[("Location", "http://10.3.8.216/error")], ""),
+        ]));
+        let error = login_with(&mut client, &probes, "user", "pass").unwrap_err();
+        assert_eq!(
+            classify(&error),
+            Some(&BuptError::UnexpectedRedirect(
+                "http://10.3.8.216/error".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn still_online_after_logout_is_typed() {
+        let probes = [Probe::generate_204(CHECK_URL)];
+        let mut client = Scripted(VecDeque::from([
+            response(200, &[], ""),
+            response(204, &[], ""),
+        ]));
+        let error = logout_with(&mut client, &probes).unwrap_err();
+        assert_eq!(classify(&error), Some(&BuptError::StillOnline));
+    }
+}
@@ -0,0 +1,397 @@
+use anyhow::{bail, Result};
+use std::{
+    future::{poll_fn, Future},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    task::Poll,
+    thread,
+};
+
+use super::{
+    check_with, check_with_async,
+    http::{block_on, Blocking, Response},
+    AsyncHttpClient, CheckResult, HttpClient, Method,
+};
+
+/// Every racing probe runs its own HTTP client.
+const PROBE_STACK_SIZE: usize = 8192;
+/// Upper bound for configured probes, each may run on its own thread.
+pub const MAX_PROBES: usize = 6;
+
+/// What a probe answers when the device is online.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Expect {
+    Status(u16),
+    BodyContains { status: u16, text: String },
+}
+
+impl Expect {
+    pub fn matches(&self, response: &Response) -> bool {
+        match self {
+            Expect::Status(status) => response.status == *status,
+            Expect::BodyContains { status, text } => {
+                response.status == *status
+                    && String::from_utf8_lossy(&response.body).contains(text.as_str())
+            }
+        }
+    }
+}
+
+/// A connectivity check. Only the first, unredirected response is compared
+/// with `expect`; redirects are followed to find the portal login page.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Probe {
+    pub url: String,
+    pub method: Method,
+    pub expect: Expect,
+}
+
+impl Probe {
+    pub fn generate_204(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            method: Method::Get,
+            expect: Expect::Status(204),
+        }
+    }
+
+    pub fn hotspot_detect(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            method: Method::Get,
+            expect: Expect::BodyContains {
+                status: 200,
+                text: "Success".to_string(),
+            },
+        }
+    }
+
+    /// A page that is only served by the real site, recognized by `marker`.
+    /// Portals intercept with a `200` too, so the status alone proves nothing.
+    pub fn page(url: impl Into<String>, marker: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            method: Method::Get,
+            expect: Expect::BodyContains {
+                status: 200,
+                text: marker.into(),
+            },
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        super::http::split_url(&self.url)?;
+        if self.method == Method::Post {
+            bail!("检测地址不支持 POST: {}", self.url);
+        }
+        match &self.expect {
+            Expect::Status(200) => {
+                bail!("状态码 200 无法区分认证页面，请检查页面内容: {}", self.url)
+            }
+            Expect::BodyContains { text, .. } if self.method == Method::Head || text.is_empty() => {
+                bail!("检查页面内容需要 GET 请求和非空的内容: {}", self.url)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Try the probes in order until one gives a verdict.
+    Sequential,
+    /// Run all probes at once and take the first verdict.
+    Race,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct ProbeConfig {
+    pub strategy: Strategy,
+    pub probes: Vec<Probe>,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            strategy: Strategy::Sequential,
+            probes: vec![
+                Probe::generate_204(super::CHECK_URL),
+                Probe::hotspot_detect("http://captive.apple.com/hotspot-detect.html"),
+                Probe::page("http://www.baidu.com/", "百度一下"),
+            ],
+        }
+    }
+}
+
+impl ProbeConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.probes.is_empty() || self.probes.len() > MAX_PROBES {
+            bail!("检测地址数量应为 1-{}", MAX_PROBES);
+        }
+        self.probes.iter().try_for_each(Probe::validate)
+    }
+}
+
+fn no_probes() -> anyhow::Error {
+    anyhow::anyhow!("no connectivity probe configured")
+}
+
+/// Runs the probes one after another, an unreachable or misbehaving probe
+/// only moves on to the next one.
+pub fn check_sequential(client: &mut impl HttpClient, probes: &[Probe]) -> Result<CheckResult> {
+    block_on(check_sequential_async(&mut Blocking(client), probes))
+}
+
+/// Async [`check_sequential`].
+pub async fn check_sequential_async(
+    client: &mut impl AsyncHttpClient,
+    probes: &[Probe],
+) -> Result<CheckResult> {
+    let mut last_error = None;
+    for probe in probes {
+        match check_with_async(client, probe).await {
+            Ok(result) => return Ok(result),
+            Err(e) => {
+                log::warn!("Probe {} failed: {}", probe.url, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(no_probes))
+}
+
+/// Fails every request once `cancelled` is set, so a probe that lost the
+/// race stops before its next request.
+struct Cancellable<C> {
+    client: C,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<C: HttpClient> HttpClient for Cancellable<C> {
+    fn request(
+        &mut self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Response> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            bail!("probe cancelled");
+        }
+        self.client.request(method, url, headers, body)
+    }
+}
+
+/// Sets the flag when the race is over, however it ends.
+struct Cancel(Arc<AtomicBool>);
+
+impl Drop for Cancel {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Runs every probe on its own thread and returns the first verdict. The
+/// other probes are cancelled; a request already in flight still runs to
+/// its timeout.
+pub fn check_race<C, F>(new_client: F, probes: &[Probe]) -> Result<CheckResult>
+where
+    C: HttpClient,
+    F: Fn() -> Result<C> + Send + Sync + 'static,
+{
+    let new_client = Arc::new(new_client);
+    let cancel = Cancel(Arc::new(AtomicBool::new(false)));
+    let (tx, rx) = mpsc::channel();
+    for probe in probes.iter().cloned() {
+        let tx = tx.clone();
+        let new_client = Arc::clone(&new_client);
+        let cancelled = Arc::clone(&cancel.0);
+        thread::Builder::new()
+            .stack_size(PROBE_STACK_SIZE)
+            .spawn(move || {
+                let result = new_client().and_then(|client| {
+                    let mut client = Cancellable {
+                        client,
+                        cancelled: Arc::clone(&cancelled),
+                    };
+                    check_with(&mut client, &probe)
+                });
+                if cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                if let Err(e) = &result {
+                    log::warn!("Probe {} failed: {}", probe.url, e);
+                }
+                // The receiver is gone once another probe won.
+                let _ = tx.send(result);
+            })?;
+    }
+    drop(tx);
+
+    let mut last_error = None;
+    for result in rx {
+        match result {
+            Ok(result) => return Ok(result),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(no_probes))
+}
+
+/// Runs every probe at once on the calling task and returns the first
+/// verdict. The other checks are dropped, in the middle of their request.
+pub async fn check_race_async<C, F>(mut new_client: F, probes: &[Probe]) -> Result<CheckResult>
+where
+    C: AsyncHttpClient,
+    F: FnMut() -> Result<C>,
+{
+    let mut checks: Vec<_> = probes
+        .iter()
+        .map(|probe| {
+            let client = new_client();
+            Some(Box::pin(async move {
+                let result = match client {
+                    Ok(mut client) => check_with_async(&mut client, probe).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = &result {
+                    log::warn!("Probe {} failed: {}", probe.url, e);
+                }
+                result
+            }))
+        })
+        .collect();
+    let mut last_error = None;
+    poll_fn(|context| {
+        for slot in checks.iter_mut() {
+            let Some(check) = slot else {
+                continue;
+            };
+            if let Poll::Ready(result) = check.as_mut().poll(context) {
+                *slot = None;
+                match result {
+                    Ok(result) => return Poll::Ready(Ok(result)),
+                    Err(e) => last_error = Some(e),
+                }
+            }
+        }
+        match checks.iter().all(Option::is_none) {
+            true => Poll::Ready(Err(last_error.take().unwrap_or_else(no_probes))),
+            false => Poll::Pending,
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::AtomicUsize,
+        time::{Duration, Instant},
+    };
+
+    /// Redirects forever, slowly, counting its requests.
+    struct Slow(Arc<AtomicUsize>);
+
+    impl HttpClient for Slow {
+        fn request(
+            &mut self,
+            _: Method,
+            url: &str,
+            _: &[(&str, &str)],
+            _: &[u8],
+        ) -> Result<Response> {
+            thread::sleep(Duration::from_millis(50));
+            self.0.fetch_add(1, Ordering::Relaxed);
+            let next = url.len() % 1000;
+            Ok(Response {
+                status: 302,
+                headers: vec![("Location".to_string(), format!("/{}", next))],
+                body: Vec::new(),
+            })
+        }
+    }
+
+    /// Answers `204` right away.
+    struct Online;
+
+    impl HttpClient for Online {
+        fn request(
+            &mut self,
+            _: Method,
+            _: &str,
+            _: &[(&str, &str)],
+            _: &[u8],
+        ) -> Result<Response> {
+            Ok(Response {
+                status: 204,
+                headers: Vec::new(),
+                body: Vec::new(),
+            })
+        }
+    }
+
+    enum Either {
+        Slow(Slow),
+        Online(Online),
+    }
+
+    impl HttpClient for Either {
+        fn request(
+            &mut self,
+            method: Method,
+            url: &str,
+            headers: &[(&str, &str)],
+            body: &[u8],
+        ) -> Result<Response> {
+            match self {
+                Either::Slow(client) => client.request(method, url, headers, body),
+                Either::Online(client) => client.request(method, url, headers, body),
+            }
+        }
+    }
+
+    #[test]
+    fn race_cancels_the_losers() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let started = Arc::new(AtomicUsize::new(0));
+        // The first client is the slow one, the second answers at once.
+        let new_client = move || {
+            Ok(match started.fetch_add(1, Ordering::Relaxed) {
+                0 => Either::Slow(Slow(Arc::clone(&counter))),
+                _ => Either::Online(Online),
+            })
+        };
+        let probes = [
+            Probe::generate_204("http://slow.example/"),
+            Probe::generate_204("http://fast.example/"),
+        ];
+        let start = Instant::now();
+        let result = check_race(new_client, &probes).unwrap();
+        assert!(matches!(result.status, crate::BuptNetStatus::Authenticated));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let before = requests.load(Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(300));
+        // At most the request in flight when the race ended.
+        assert!(requests.load(Ordering::Relaxed) <= before + 1);
+    }
+
+    #[test]
+    fn bare_200_is_rejected() {
+        let mut config = ProbeConfig::default();
+        assert!(config.validate().is_ok());
+        config.probes.push(Probe {
+            url: "http://www.baidu.com/".to_string(),
+            method: Method::Head,
+            expect: Expect::Status(200),
+        });
+        assert!(config.validate().is_err());
+    }
+}
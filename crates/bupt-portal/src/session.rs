@@ -0,0 +1,84 @@
+/// Session info shown by the portal once the device is logged in.
+///
+/// Every field except `online` is optional, the portal only renders what
+/// applies to the account.
+#[derive(serde::Serialize, Debug, Clone, Default)]
+pub struct SessionStatus {
+    pub online: bool,
+    pub account: Option<String>,
+    pub ip: Option<String>,
+    pub used_traffic: Option<String>,
+    pub used_time: Option<String>,
+    pub balance: Option<String>,
+}
+
+impl SessionStatus {
+    pub fn offline() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(body: &str) -> Self {
+        Self {
+            online: true,
+            account: field(body, &["账号", "用户名"]),
+            ip: field(body, &["IP地址", "IP 地址", "IP"]),
+            used_traffic: field(body, &["已用流量"]),
+            used_time: field(body, &["已用时长", "在线时长"]),
+            balance: field(body, &["余额", "账户余额"]),
+        }
+    }
+}
+
+/// Finds the value of the first of `labels` found, e.g. the value cell of
+/// `<td>已用流量：</td><td>1.2 GB</td>` or the rest of `<p>账号：2024</p>`.
+/// The label has to be the whole text before the colon, so `IP` doesn't
+/// match `IP地址` or `IPv6`.
+fn field(body: &str, labels: &[&str]) -> Option<String> {
+    let texts: Vec<&str> = text_nodes(body)
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .collect();
+    labels.iter().find_map(|label| {
+        texts.iter().enumerate().find_map(|(i, text)| {
+            let rest = text.strip_prefix(label)?;
+            let value = match rest.trim_start().strip_prefix([':', '：']) {
+                Some(value) => value.trim(),
+                None if rest.is_empty() => "",
+                None => return None,
+            };
+            match value {
+                "" => texts.get(i + 1).map(|text| text.to_string()),
+                value => Some(value.to_string()),
+            }
+        })
+    })
+}
+
+fn text_nodes(html: &str) -> impl Iterator<Item = &str> {
+    html.split('<').enumerate().map(|(i, chunk)| match i {
+        // Text before the first tag
+        0 => chunk,
+        _ => chunk.split_once('>').map_or("", |(_, text)| text),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_has_to_match_exactly() {
+        let body = "<tr><td>IPv6：</td><td>2001:db8::1</td></tr>\
+                    <tr><td>IP地址：</td><td>10.128.0.2</td></tr>";
+        assert_eq!(field(body, &["IP"]), None);
+        assert_eq!(field(body, &["IP地址"]).as_deref(), Some("10.128.0.2"));
+    }
+
+    #[test]
+    fn value_may_follow_the_colon() {
+        let body = "<p>账号：2024000000</p><p>余额: 12.00 元</p>";
+        let status = SessionStatus::parse(body);
+        assert_eq!(status.account.as_deref(), Some("2024000000"));
+        assert_eq!(status.balance.as_deref(), Some("12.00 元"));
+    }
+}
@@ -0,0 +1,136 @@
+//! The async portal flow against `tools/portal-emulator`, over async sockets
+//! on a single thread.
+
+use async_io::{block_on, Async, Timer};
+use bupt_portal::{
+    check_race_async, check_sequential_async, classify, encode_request, login_with_async,
+    parse_response, split_url, AsyncHttpClient, BuptError, BuptNetStatus, Method, Probe,
+    ProbeConfig, Response, MAX_RESPONSE_LEN,
+};
+use futures_lite::{future, AsyncReadExt, AsyncWriteExt};
+use portal_emulator::{Options, Scenario};
+use std::{
+    net::{SocketAddr, TcpStream},
+    time::{Duration, Instant},
+};
+
+const USER: &str = "2024000000";
+const PASS: &str = "password";
+const DELAY: Duration = Duration::from_millis(500);
+
+fn emulator(scenario: Scenario) -> SocketAddr {
+    portal_emulator::spawn(Options {
+        listen: "127.0.0.1:0".to_string(),
+        scenario,
+        user: USER.to_string(),
+        pass: PASS.to_string(),
+        delay: DELAY,
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+/// Sends every request to the emulator at `addr`, whatever the host.
+struct Client {
+    addr: SocketAddr,
+    timeout: Duration,
+}
+
+impl Client {
+    fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            timeout: Duration::from_secs(20),
+        }
+    }
+
+    async fn exchange(
+        &self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> anyhow::Result<Response> {
+        let (host, path) = split_url(url)?;
+        let mut stream = Async::<TcpStream>::connect(self.addr).await?;
+        stream
+            .write_all(&encode_request(method, host, path, headers, body))
+            .await?;
+        let mut data = Vec::new();
+        stream
+            .take(MAX_RESPONSE_LEN as u64)
+            .read_to_end(&mut data)
+            .await?;
+        parse_response(&data)
+    }
+}
+
+impl AsyncHttpClient for Client {
+    async fn request(
+        &mut self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> anyhow::Result<Response> {
+        let timeout = async {
+            Timer::after(self.timeout).await;
+            Err(anyhow::anyhow!("timed out"))
+        };
+        future::or(self.exchange(method, url, headers, body), timeout).await
+    }
+}
+
+#[test]
+fn login_goes_online() {
+    let mut client = Client::new(emulator(Scenario::Normal));
+    let probes = ProbeConfig::default().probes;
+    block_on(async {
+        login_with_async(&mut client, &probes, USER, PASS)
+            .await
+            .unwrap();
+        let result = check_sequential_async(&mut client, &probes).await.unwrap();
+        assert!(matches!(result.status, BuptNetStatus::Authenticated));
+    });
+}
+
+#[test]
+fn wrong_password_is_typed() {
+    let mut client = Client::new(emulator(Scenario::WrongPassword));
+    let probes = ProbeConfig::default().probes;
+    let error = block_on(login_with_async(&mut client, &probes, USER, PASS)).unwrap_err();
+    assert!(matches!(
+        classify(&error),
+        Some(BuptError::WrongCredentials(_))
+    ));
+}
+
+#[test]
+fn race_runs_the_probes_at_once() {
+    let addr = emulator(Scenario::Slow);
+    // Two probes fail after one slow response, the last one needs two.
+    let probes = [
+        Probe::generate_204("http://connect.rom.miui.com/missing"),
+        Probe::generate_204("http://captive.apple.com/missing"),
+        Probe::generate_204(bupt_portal::CHECK_URL),
+    ];
+    let start = Instant::now();
+    let result = block_on(check_race_async(|| Ok(Client::new(addr)), &probes)).unwrap();
+    assert!(matches!(result.status, BuptNetStatus::NotAuthenticated(_)));
+    // One after another, it would take four delays.
+    assert!(start.elapsed() < DELAY * 3);
+}
+
+#[test]
+fn slow_portal_times_out() {
+    let mut client = Client {
+        timeout: DELAY / 5,
+        ..Client::new(emulator(Scenario::Slow))
+    };
+    let probes = ProbeConfig::default().probes;
+    let error = block_on(check_sequential_async(&mut client, &probes)).unwrap_err();
+    assert!(matches!(
+        classify(&error),
+        Some(BuptError::PortalUnreachable(_))
+    ));
+}
@@ -0,0 +1,183 @@
+//! The portal flow against `tools/portal-emulator`, one emulator per test.
+
+use bupt_portal::{
+    check_race, check_with, classify, login_with, logout_with, status_with, BuptError,
+    BuptNetStatus, Probe, ProbeConfig, StdHttpClient,
+};
+use portal_emulator::{Options, Scenario};
+use std::{net::SocketAddr, time::Duration};
+
+const USER: &str = "2024000000";
+const PASS: &str = "password";
+
+/// How long the `Slow` scenario holds back every response.
+const DELAY: Duration = Duration::from_millis(500);
+
+fn emulator(scenario: Scenario) -> SocketAddr {
+    portal_emulator::spawn(Options {
+        listen: "127.0.0.1:0".to_string(),
+        scenario,
+        user: USER.to_string(),
+        pass: PASS.to_string(),
+        delay: DELAY,
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+/// A client sending every host the probes and the portal use to `addr`.
+fn client_for(addr: SocketAddr) -> StdHttpClient {
+    [
+        "connect.rom.miui.com",
+        "captive.apple.com",
+        "www.baidu.com",
+        "10.3.8.216",
+    ]
+    .into_iter()
+    .fold(StdHttpClient::new(), |client, host| {
+        client.resolve(host, addr)
+    })
+}
+
+/// A client for a fresh emulator.
+fn client(scenario: Scenario) -> StdHttpClient {
+    client_for(emulator(scenario))
+}
+
+fn probes() -> Vec<Probe> {
+    ProbeConfig::default().probes
+}
+
+#[test]
+fn generate_204_means_online() {
+    let result = check_with(&mut client(Scenario::Authenticated), &probes()[0]).unwrap();
+    assert!(matches!(result.status, BuptNetStatus::Authenticated));
+    assert_eq!(result.chain, [bupt_portal::CHECK_URL]);
+}
+
+#[test]
+fn redirect_chain_ends_at_the_login_page() {
+    let result = check_with(&mut client(Scenario::Normal), &probes()[0]).unwrap();
+    let BuptNetStatus::NotAuthenticated(cookie) = result.status else {
+        panic!("expected the login page");
+    };
+    assert!(cookie.unwrap().starts_with("PHPSESSID=emulator"));
+    assert_eq!(
+        result.chain,
+        [bupt_portal::CHECK_URL, "http://10.3.8.216/index"]
+    );
+}
+
+#[test]
+fn hotspot_detect_redirects_too() {
+    let result = check_with(&mut client(Scenario::Normal), &probes()[1]).unwrap();
+    assert!(matches!(
+        result.status,
+        BuptNetStatus::NotAuthenticated(Some(_))
+    ));
+}
+
+#[test]
+fn home_page_probe_checks_the_marker() {
+    let result = check_with(&mut client(Scenario::Authenticated), &probes()[2]).unwrap();
+    assert!(matches!(result.status, BuptNetStatus::Authenticated));
+
+    let result = check_with(&mut client(Scenario::Normal), &probes()[2]).unwrap();
+    assert!(matches!(result.status, BuptNetStatus::NotAuthenticated(_)));
+}
+
+#[test]
+fn race_finds_the_login_page() {
+    let addr = emulator(Scenario::Normal);
+    let result = check_race(move || Ok(client_for(addr)), &probes()).unwrap();
+    assert!(matches!(
+        result.status,
+        BuptNetStatus::NotAuthenticated(Some(_))
+    ));
+}
+
+#[test]
+fn login_with_cookie_goes_online() {
+    let mut client = client(Scenario::Normal);
+    login_with(&mut client, &probes(), USER, PASS).unwrap();
+    let result = check_with(&mut client, &probes()[0]).unwrap();
+    assert!(matches!(result.status, BuptNetStatus::Authenticated));
+
+    let status = status_with(&mut client, &probes()).unwrap();
+    assert!(status.online);
+    assert_eq!(status.account.as_deref(), Some(USER));
+}
+
+#[test]
+fn logout_ends_the_session() {
+    let mut client = client(Scenario::Normal);
+    login_with(&mut client, &probes(), USER, PASS).unwrap();
+    logout_with(&mut client, &probes()).unwrap();
+    assert!(!status_with(&mut client, &probes()).unwrap().online);
+}
+
+#[test]
+fn wrong_password_keeps_the_portal_message() {
+    let error =
+        login_with(&mut client(Scenario::WrongPassword), &probes(), USER, PASS).unwrap_err();
+    assert_eq!(
+        classify(&error),
+        Some(&BuptError::WrongCredentials("账号或密码错误".to_string()))
+    );
+    assert!(classify(&error).unwrap().is_permanent());
+}
+
+#[test]
+fn login_without_cookie_is_retried() {
+    let error = login_with(&mut client(Scenario::NoCookie), &probes(), USER, PASS).unwrap_err();
+    assert_eq!(classify(&error), Some(&BuptError::MissingCookie));
+    assert!(!classify(&error).unwrap().is_permanent());
+}
+
+#[test]
+fn rejection_without_cookie_keeps_the_reason() {
+    let error =
+        login_with(&mut client(Scenario::TooManyDevices), &probes(), USER, PASS).unwrap_err();
+    assert_eq!(
+        classify(&error),
+        Some(&BuptError::TooManyDevices(
+            "在线终端数量已达上限".to_string()
+        ))
+    );
+}
+
+#[test]
+fn redirect_loop_is_detected() {
+    let error = check_with(&mut client(Scenario::RedirectLoop), &probes()[0]).unwrap_err();
+    let Some(BuptError::RedirectLoop(chain)) = classify(&error) else {
+        panic!("expected a redirect loop, got {}", error);
+    };
+    assert_eq!(chain.last().unwrap(), "http://10.3.8.216/loop");
+}
+
+#[test]
+fn unreachable_portal_is_reported() {
+    let mut client =
+        StdHttpClient::new().resolve("connect.rom.miui.com", "127.0.0.1:1".parse().unwrap());
+    let error = check_with(&mut client, &probes()[0]).unwrap_err();
+    assert!(matches!(
+        classify(&error),
+        Some(BuptError::PortalUnreachable(_))
+    ));
+}
+
+#[test]
+fn slow_portal_times_out() {
+    let mut client = client(Scenario::Slow).timeout(Some(DELAY / 5));
+    let error = check_with(&mut client, &probes()[0]).unwrap_err();
+    assert!(matches!(
+        classify(&error),
+        Some(BuptError::PortalUnreachable(_))
+    ));
+}
+
+#[test]
+fn slow_portal_within_the_timeout_logs_in() {
+    let mut client = client(Scenario::Slow).timeout(Some(DELAY * 10));
+    login_with(&mut client, &probes(), USER, PASS).unwrap();
+}
@@ -0,0 +1,318 @@
+//! A small cache of upstream answers, honouring the record TTLs.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::message::{Message, Question, Record, RCODE_NOERROR, RCODE_NXDOMAIN, TYPE_OPT};
+
+/// Answers without records, NODATA and NXDOMAIN, are kept this long at most.
+const NEGATIVE_TTL: u32 = 60;
+/// Upper bound for any entry, so changes upstream show up eventually.
+const MAX_TTL: u32 = 3600;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    /// Lowercase, names are case-insensitive.
+    name: String,
+    qtype: u16,
+    qclass: u16,
+}
+
+impl From<&Question> for Key {
+    fn from(question: &Question) -> Self {
+        Self {
+            name: question.name.to_ascii_lowercase(),
+            qtype: question.qtype,
+            qclass: question.qclass,
+        }
+    }
+}
+
+struct Entry {
+    response: Message,
+    stored: Instant,
+    expires: Instant,
+}
+
+pub struct Cache {
+    entries: HashMap<Key, Entry>,
+    capacity: usize,
+}
+
+impl Cache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity,
+        }
+    }
+
+    /// Stores an upstream response to a single question. Truncated and failed
+    /// responses are not cached.
+    pub fn insert(&mut self, response: &Message, now: Instant) {
+        let [question] = response.questions.as_slice() else {
+            return;
+        };
+        if response.is_truncated() || !matches!(response.rcode(), RCODE_NOERROR | RCODE_NXDOMAIN) {
+            return;
+        }
+        let ttl = match Self::records(response).map(|record| record.ttl).min() {
+            Some(ttl) if !response.answers.is_empty() => ttl.min(MAX_TTL),
+            Some(ttl) => ttl.min(NEGATIVE_TTL),
+            None => NEGATIVE_TTL,
+        };
+        if ttl == 0 {
+            return;
+        }
+
+        if self.entries.len() >= self.capacity {
+            self.entries.retain(|_, entry| entry.expires > now);
+        }
+        if self.entries.len() >= self.capacity {
+            // Still full, make room by dropping the entry closest to expiring.
+            if let Some(key) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires)
+                .map(|(key, _)| key.clone())
+            {
+                self.entries.remove(&key);
+            }
+        }
+
+        let mut response = response.clone();
+        response
+            .additionals
+            .retain(|record| record.rtype != TYPE_OPT);
+        self.entries.insert(
+            Key::from(question),
+            Entry {
+                response,
+                stored: now,
+                expires: now + Duration::from_secs(ttl as u64),
+            },
+        );
+    }
+
+    /// The cached response to `question`, with the TTLs counted down by the
+    /// time it spent in the cache. The header is the upstream one, the
+    /// caller sets the ID.
+    pub fn get(&mut self, question: &Question, now: Instant) -> Option<Message> {
+        let key = Key::from(question);
+        let entry = self.entries.get(&key)?;
+        if entry.expires <= now {
+            self.entries.remove(&key);
+            return None;
+        }
+
+        let elapsed = now.duration_since(entry.stored).as_secs() as u32;
+        let mut response = entry.response.clone();
+        for record in response
+            .answers
+            .iter_mut()
+            .chain(&mut response.authorities)
+            .chain(&mut response.additionals)
+        {
+            record.ttl = record.ttl.saturating_sub(elapsed);
+        }
+        Some(response)
+    }
+
+    fn records(response: &Message) -> impl Iterator<Item = &Record> {
+        response
+            .answers
+            .iter()
+            .chain(&response.authorities)
+            .chain(&response.additionals)
+            .filter(|record| record.rtype != TYPE_OPT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{CLASS_IN, RCODE_SERVFAIL, TYPE_A, TYPE_SOA};
+
+    fn question(name: &str) -> Question {
+        Question {
+            name: name.to_string(),
+            qtype: TYPE_A,
+            qclass: CLASS_IN,
+        }
+    }
+
+    fn response(name: &str, rcode: u8, answers: &[u32]) -> Message {
+        let query = Message {
+            questions: vec![question(name)],
+            ..Default::default()
+        };
+        let mut response = query.response(rcode);
+        response.answers = answers
+            .iter()
+            .map(|&ttl| Record::a(name, ttl, [10, 0, 0, 1]))
+            .collect();
+        response
+    }
+
+    fn soa(ttl: u32) -> Record {
+        Record {
+            name: "example.com".to_string(),
+            rtype: TYPE_SOA,
+            class: CLASS_IN,
+            ttl,
+            // Root MNAME and RNAME, then the five counters.
+            data: vec![0; 22],
+        }
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn ttls_count_down() {
+        let mut cache = Cache::new(8);
+        let now = Instant::now();
+        cache.insert(&response("example.com", RCODE_NOERROR, &[300, 120]), now);
+
+        let cached = cache.get(&question("example.com"), now).unwrap();
+        assert_eq!(cached.answers[0].ttl, 300);
+        let cached = cache
+            .get(&question("example.com"), now + secs(100))
+            .unwrap();
+        assert_eq!(cached.answers[0].ttl, 200);
+        assert_eq!(cached.answers[1].ttl, 20);
+
+        // The entry lives as long as its shortest record.
+        assert!(cache
+            .get(&question("example.com"), now + secs(120))
+            .is_none());
+    }
+
+    #[test]
+    fn ttls_are_capped() {
+        let mut cache = Cache::new(8);
+        let now = Instant::now();
+        cache.insert(&response("example.com", RCODE_NOERROR, &[86400]), now);
+        assert!(cache
+            .get(&question("example.com"), now + secs(MAX_TTL as u64 - 1))
+            .is_some());
+        assert!(cache
+            .get(&question("example.com"), now + secs(MAX_TTL as u64))
+            .is_none());
+    }
+
+    #[test]
+    fn names_are_case_insensitive() {
+        let mut cache = Cache::new(8);
+        let now = Instant::now();
+        cache.insert(&response("Example.COM", RCODE_NOERROR, &[300]), now);
+        assert!(cache.get(&question("example.com"), now).is_some());
+    }
+
+    #[test]
+    fn negative_answers_are_cached_briefly() {
+        let mut cache = Cache::new(8);
+        let now = Instant::now();
+
+        let mut nxdomain = response("nx.example.com", RCODE_NXDOMAIN, &[]);
+        nxdomain.authorities.push(soa(900));
+        cache.insert(&nxdomain, now);
+        let cached = cache.get(&question("nx.example.com"), now).unwrap();
+        assert_eq!(cached.rcode(), RCODE_NXDOMAIN);
+        assert!(cache
+            .get(
+                &question("nx.example.com"),
+                now + secs(NEGATIVE_TTL as u64 - 1)
+            )
+            .is_some());
+        assert!(cache
+            .get(&question("nx.example.com"), now + secs(NEGATIVE_TTL as u64))
+            .is_none());
+
+        // NODATA without an SOA.
+        cache.insert(&response("nodata.example.com", RCODE_NOERROR, &[]), now);
+        assert!(cache.get(&question("nodata.example.com"), now).is_some());
+        assert!(cache
+            .get(
+                &question("nodata.example.com"),
+                now + secs(NEGATIVE_TTL as u64)
+            )
+            .is_none());
+
+        // A shorter SOA minimum wins.
+        let mut nxdomain = response("short.example.com", RCODE_NXDOMAIN, &[]);
+        nxdomain.authorities.push(soa(5));
+        cache.insert(&nxdomain, now);
+        assert!(cache
+            .get(&question("short.example.com"), now + secs(5))
+            .is_none());
+    }
+
+    #[test]
+    fn failures_are_not_cached() {
+        let mut cache = Cache::new(8);
+        let now = Instant::now();
+
+        cache.insert(&response("fail.example.com", RCODE_SERVFAIL, &[]), now);
+        assert!(cache.get(&question("fail.example.com"), now).is_none());
+
+        let mut truncated = response("tc.example.com", RCODE_NOERROR, &[300]);
+        truncated.flags |= 0x0200;
+        cache.insert(&truncated, now);
+        assert!(cache.get(&question("tc.example.com"), now).is_none());
+
+        cache.insert(&response("zero.example.com", RCODE_NOERROR, &[0]), now);
+        assert!(cache.get(&question("zero.example.com"), now).is_none());
+
+        let mut two = response("two.example.com", RCODE_NOERROR, &[300]);
+        two.questions.push(question("other.example.com"));
+        cache.insert(&two, now);
+        assert!(cache.get(&question("two.example.com"), now).is_none());
+    }
+
+    #[test]
+    fn opt_is_not_cached() {
+        let mut cache = Cache::new(8);
+        let now = Instant::now();
+        let mut response = response("example.com", RCODE_NOERROR, &[300]);
+        response.additionals.push(Record {
+            name: String::new(),
+            rtype: TYPE_OPT,
+            class: 1232,
+            ttl: 0,
+            data: Vec::new(),
+        });
+        cache.insert(&response, now);
+        let cached = cache.get(&question("example.com"), now).unwrap();
+        assert!(cached.opt().is_none());
+    }
+
+    #[test]
+    fn expired_entries_make_room_first() {
+        let mut cache = Cache::new(2);
+        let now = Instant::now();
+        cache.insert(&response("short.example.com", RCODE_NOERROR, &[10]), now);
+        cache.insert(&response("long.example.com", RCODE_NOERROR, &[300]), now);
+
+        let later = now + secs(20);
+        cache.insert(&response("new.example.com", RCODE_NOERROR, &[100]), later);
+        assert!(cache.get(&question("long.example.com"), later).is_some());
+        assert!(cache.get(&question("new.example.com"), later).is_some());
+    }
+
+    #[test]
+    fn full_cache_drops_the_entry_closest_to_expiring() {
+        let mut cache = Cache::new(2);
+        let now = Instant::now();
+        cache.insert(&response("long.example.com", RCODE_NOERROR, &[300]), now);
+        cache.insert(&response("short.example.com", RCODE_NOERROR, &[100]), now);
+        cache.insert(&response("new.example.com", RCODE_NOERROR, &[200]), now);
+
+        assert!(cache.get(&question("short.example.com"), now).is_none());
+        assert!(cache.get(&question("long.example.com"), now).is_some());
+        assert!(cache.get(&question("new.example.com"), now).is_some());
+    }
+}
@@ -0,0 +1,233 @@
+//! Forwarding to the upstream resolvers of the STA side, once it is online.
+
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hasher},
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cache::Cache,
+    message::{self, Message, Question},
+    DNS_MAX_LEN,
+};
+
+/// Upstream answers not received by then are given up, and the next upstream
+/// server is used from then on.
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(3);
+/// Queries waiting for upstream at once.
+const MAX_PENDING: usize = 32;
+const CACHE_CAPACITY: usize = 64;
+const DNS_PORT: u16 = 53;
+
+struct Pending {
+    client: SocketAddr,
+    /// The ID the client used, upstream sees a random one.
+    id: u16,
+    question: Option<Question>,
+    upstream: Ipv4Addr,
+    sent: Instant,
+}
+
+pub struct Forwarder {
+    ip: Ipv4Addr,
+    upstream: Vec<Ipv4Addr>,
+    /// Port of the upstream servers, 53 but in tests.
+    port: u16,
+    /// Index into `upstream` of the server queries go to.
+    preferred: usize,
+    local: Vec<String>,
+    cache: Cache,
+    pending: HashMap<u16, Pending>,
+    /// Seeded randomly, hashes a counter into the upstream IDs.
+    random: RandomState,
+    counter: u64,
+}
+
+impl Forwarder {
+    /// Forwards to `upstream`, except for the `local` names, which resolve to
+    /// `ip`.
+    pub fn new(ip: Ipv4Addr, upstream: Vec<Ipv4Addr>, local: Vec<String>) -> Self {
+        Self {
+            ip,
+            upstream,
+            port: DNS_PORT,
+            preferred: 0,
+            local,
+            cache: Cache::new(CACHE_CAPACITY),
+            pending: HashMap::new(),
+            random: RandomState::new(),
+            counter: 0,
+        }
+    }
+
+    /// Sends to `port` on the upstream servers instead of 53.
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn upstream(&self) -> &[Ipv4Addr] {
+        &self.upstream
+    }
+
+    /// Handles a datagram received on the server socket, either a client
+    /// query or an upstream answer to relay.
+    pub fn handle(&mut self, socket: &UdpSocket, packet: &[u8], from: SocketAddr) {
+        let now = Instant::now();
+        self.expire(now);
+        if self.is_upstream(from) {
+            self.relay(socket, packet, from, now);
+            return;
+        }
+
+        let query = match crate::parse_query(packet) {
+            Ok(query) => query,
+            Err(Some(reply)) => return send(socket, &reply, from),
+            Err(None) => return,
+        };
+        if let [question] = query.questions.as_slice() {
+            if self.is_local(&question.name) {
+                if let Some(reply) =
+                    crate::encode_reply(&query, crate::answer_locally(&query, self.ip))
+                {
+                    send(socket, &reply, from);
+                }
+                return;
+            }
+            if let Some(cached) = self.cache.get(question, now) {
+                log::debug!("DNS cache hit: {:?}", question);
+                let mut response = query.response(cached.rcode());
+                response.flags &= !message::FLAG_AA;
+                response.answers = cached.answers;
+                response.authorities = cached.authorities;
+                response.additionals.extend(cached.additionals);
+                if let Some(reply) = crate::encode_reply(&query, response) {
+                    send(socket, &reply, from);
+                }
+                return;
+            }
+        }
+        self.forward(socket, query, from, now);
+    }
+
+    /// Gives up on queries upstream didn't answer in time.
+    pub fn expire(&mut self, now: Instant) {
+        let before = self.pending.len();
+        self.pending
+            .retain(|_, pending| now.duration_since(pending.sent) < FORWARD_TIMEOUT);
+        if self.pending.len() < before && self.upstream.len() > 1 {
+            self.preferred = (self.preferred + 1) % self.upstream.len();
+            log::warn!(
+                "DNS server timed out, switching to {}",
+                self.upstream[self.preferred]
+            );
+        }
+    }
+
+    fn forward(&mut self, socket: &UdpSocket, query: Message, from: SocketAddr, now: Instant) {
+        let Some(&upstream) = self.upstream.get(self.preferred) else {
+            return self.fail(socket, &query, from);
+        };
+        if self.pending.len() >= MAX_PENDING {
+            log::warn!("Too many pending DNS queries");
+            return self.fail(socket, &query, from);
+        }
+        let id = loop {
+            let id = self.random_id();
+            if !self.pending.contains_key(&id) {
+                break id;
+            }
+        };
+
+        let mut upstream_query = query.clone();
+        upstream_query.id = id;
+        // Answers larger than the receive buffer would be cut off.
+        for record in &mut upstream_query.additionals {
+            if record.rtype == message::TYPE_OPT {
+                record.class = record.class.min(DNS_MAX_LEN as u16);
+            }
+        }
+        let Ok(bytes) = upstream_query.to_bytes() else {
+            return self.fail(socket, &query, from);
+        };
+        if let Err(e) = socket.send_to(&bytes, (upstream, self.port)) {
+            log::warn!("Failed to forward DNS query: {}", e);
+            return self.fail(socket, &query, from);
+        }
+        self.pending.insert(
+            id,
+            Pending {
+                client: from,
+                id: query.id,
+                question: query.questions.into_iter().next(),
+                upstream,
+                sent: now,
+            },
+        );
+    }
+
+    /// Passes an upstream answer on to the client that asked, and caches it.
+    fn relay(&mut self, socket: &UdpSocket, packet: &[u8], from: SocketAddr, now: Instant) {
+        let Some(header) = Message::parse_header(packet) else {
+            return;
+        };
+        let expected = self
+            .pending
+            .get(&header.id)
+            .is_some_and(|pending| IpAddr::V4(pending.upstream) == from.ip());
+        if !header.is_response() || !expected {
+            log::debug!("Unexpected DNS message from {}", from);
+            return;
+        }
+        let pending = self.pending.remove(&header.id).unwrap();
+
+        let mut reply = packet.to_vec();
+        reply[..2].copy_from_slice(&pending.id.to_be_bytes());
+        send(socket, &reply, pending.client);
+
+        match Message::parse(packet) {
+            Ok(response) if response.questions.first() == pending.question.as_ref() => {
+                self.cache.insert(&response, now)
+            }
+            Ok(_) => log::debug!("DNS answer for another question from {}", from),
+            Err(e) => log::debug!("Malformed DNS answer from {}: {}", from, e),
+        }
+    }
+
+    fn fail(&self, socket: &UdpSocket, query: &Message, client: SocketAddr) {
+        if let Ok(reply) = query.response(message::RCODE_SERVFAIL).to_bytes() {
+            send(socket, &reply, client);
+        }
+    }
+
+    /// Whether `from` is one of the upstream servers, which may answer.
+    pub fn is_upstream(&self, from: SocketAddr) -> bool {
+        from.port() == self.port
+            && self
+                .upstream
+                .iter()
+                .any(|upstream| IpAddr::V4(*upstream) == from.ip())
+    }
+
+    /// Unpredictable, so off-path answers can't be matched to a query.
+    fn random_id(&mut self) -> u16 {
+        let mut hasher = self.random.build_hasher();
+        hasher.write_u64(self.counter);
+        self.counter += 1;
+        hasher.finish() as u16
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.local
+            .iter()
+            .any(|local| local.eq_ignore_ascii_case(name))
+    }
+}
+
+fn send(socket: &UdpSocket, reply: &[u8], to: SocketAddr) {
+    if let Err(e) = socket.send_to(reply, to) {
+        log::warn!("Failed to send DNS response: {}", e);
+    }
+}
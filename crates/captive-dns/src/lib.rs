@@ -0,0 +1,249 @@
+//! The DNS side of the captive portal: message parsing and building, the
+//! replies of a server resolving every name to itself, and forwarding with a
+//! cache once there is an upstream.
+//!
+//! Kept free of ESP-IDF so it builds and tests on the host; the firmware
+//! owns the socket.
+
+mod cache;
+mod forward;
+pub mod message;
+
+use std::net::Ipv4Addr;
+
+use message::{Message, Record};
+
+pub use forward::Forwarder;
+
+/// Largest query accepted, the EDNS buffer size recommended by DNS Flag Day
+/// 2020.
+pub const DNS_MAX_LEN: usize = 1232;
+/// Replies without EDNS have to fit into a classic UDP message.
+const DNS_UDP_LEN: usize = 512;
+/// Short, so clients ask again once the device is online.
+const CAPTIVE_TTL: u32 = 10;
+
+/// Parses a query, or returns the reply to send instead, if any: FORMERR for
+/// malformed queries and NOTIMP for anything but a standard query.
+pub(crate) fn parse_query(query: &[u8]) -> Result<Message, Option<Vec<u8>>> {
+    let query = match Message::parse(query) {
+        Ok(query) => query,
+        Err(e) => {
+            log::debug!("Malformed DNS query: {}", e);
+            return match Message::parse_header(query) {
+                Some(header) if !header.is_response() => {
+                    Err(header.response(message::RCODE_FORMERR).to_bytes().ok())
+                }
+                _ => Err(None),
+            };
+        }
+    };
+    if query.is_response() {
+        return Err(None);
+    }
+    if query.opcode() != message::OPCODE_QUERY {
+        return Err(query.response(message::RCODE_NOTIMP).to_bytes().ok());
+    }
+    Ok(query)
+}
+
+/// Answers every A question with `ip`. Anything else, AAAA, HTTPS and SVCB
+/// included, gets no answer so clients fall back to IPv4.
+pub(crate) fn answer_locally(query: &Message, ip: Ipv4Addr) -> Message {
+    let mut response = query.response(message::RCODE_NOERROR);
+    for question in &query.questions {
+        log::debug!("DNS query: {:?}", question);
+        // Nothing else is served, IPv6 and service bindings in particular.
+        if (question.qtype, question.qclass) == (message::TYPE_A, message::CLASS_IN) {
+            response
+                .answers
+                .push(Record::a(&question.name, CAPTIVE_TTL, ip.octets()));
+        }
+    }
+    response
+}
+
+/// Serializes `response`, truncating it if it is larger than `query` allows.
+pub(crate) fn encode_reply(query: &Message, mut response: Message) -> Option<Vec<u8>> {
+    let limit = query
+        .opt()
+        .map_or(DNS_UDP_LEN, |opt| (opt.class as usize).max(DNS_UDP_LEN));
+    let bytes = response.to_bytes().ok()?;
+    if bytes.len() <= limit {
+        return Some(bytes);
+    }
+    response.truncate();
+    response.to_bytes().ok()
+}
+
+/// Builds the reply of the captive DNS server: every A query resolves to `ip`,
+/// anything else, AAAA, HTTPS and SVCB included, gets an empty NOERROR so
+/// clients fall back to IPv4.
+/// Returns `None` for messages that must not be answered.
+pub fn captive_reply(query: &[u8], ip: Ipv4Addr) -> Option<Vec<u8>> {
+    let query = match parse_query(query) {
+        Ok(query) => query,
+        Err(reply) => return reply,
+    };
+    encode_reply(&query, answer_locally(&query, ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::{
+        Question, CLASS_IN, RCODE_FORMERR, RCODE_NOERROR, RCODE_NOTIMP, TYPE_A, TYPE_OPT,
+    };
+
+    const IP: Ipv4Addr = Ipv4Addr::new(192, 168, 4, 1);
+    const TYPE_AAAA: u16 = 28;
+    const TYPE_HTTPS: u16 = 65;
+
+    fn query(questions: &[(&str, u16)]) -> Message {
+        Message {
+            id: 0x4242,
+            flags: 0x0100,
+            questions: questions
+                .iter()
+                .map(|&(name, qtype)| Question {
+                    name: name.to_string(),
+                    qtype,
+                    qclass: CLASS_IN,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn opt(size: u16) -> Record {
+        Record {
+            name: String::new(),
+            rtype: TYPE_OPT,
+            class: size,
+            ttl: 0,
+            data: Vec::new(),
+        }
+    }
+
+    fn reply(query: &Message) -> Message {
+        Message::parse(&captive_reply(&query.to_bytes().unwrap(), IP).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn every_a_question_is_answered() {
+        let query = query(&[("example.com", TYPE_A), ("captive.apple.com", TYPE_A)]);
+        let reply = reply(&query);
+        assert_eq!(reply.id, query.id);
+        assert!(reply.is_response());
+        assert_eq!(reply.rcode(), RCODE_NOERROR);
+        assert_eq!(reply.questions, query.questions);
+        assert_eq!(
+            reply.answers,
+            [
+                Record::a("example.com", CAPTIVE_TTL, IP.octets()),
+                Record::a("captive.apple.com", CAPTIVE_TTL, IP.octets()),
+            ]
+        );
+    }
+
+    #[test]
+    fn aaaa_and_https_get_empty_noerror() {
+        for qtype in [TYPE_AAAA, TYPE_HTTPS] {
+            let reply = reply(&query(&[("example.com", qtype)]));
+            assert_eq!(reply.rcode(), RCODE_NOERROR);
+            assert!(reply.answers.is_empty());
+            assert!(reply.authorities.is_empty());
+        }
+
+        let reply = reply(&query(&[
+            ("example.com", TYPE_AAAA),
+            ("example.com", TYPE_A),
+        ]));
+        assert_eq!(
+            reply.answers,
+            [Record::a("example.com", CAPTIVE_TTL, IP.octets())]
+        );
+    }
+
+    #[test]
+    fn edns_opt_is_echoed() {
+        let mut query = query(&[("example.com", TYPE_A)]);
+        query.additionals.push(opt(1232));
+        assert_eq!(reply(&query).additionals, [opt(1232)]);
+
+        query.additionals.clear();
+        assert!(reply(&query).additionals.is_empty());
+    }
+
+    #[test]
+    fn malformed_query_gets_formerr() {
+        let mut bytes = query(&[("example.com", TYPE_A)]).to_bytes().unwrap();
+        bytes.truncate(bytes.len() - 1);
+        let reply = Message::parse(&captive_reply(&bytes, IP).unwrap()).unwrap();
+        assert_eq!(reply.id, 0x4242);
+        assert_eq!(reply.rcode(), RCODE_FORMERR);
+
+        // Not even a header.
+        assert!(captive_reply(&bytes[..3], IP).is_none());
+    }
+
+    #[test]
+    fn pointer_loop_gets_formerr() {
+        let mut bytes = query(&[]).to_bytes().unwrap();
+        bytes[5] = 1;
+        bytes.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1]);
+        let reply = Message::parse(&captive_reply(&bytes, IP).unwrap()).unwrap();
+        assert_eq!(reply.rcode(), RCODE_FORMERR);
+    }
+
+    #[test]
+    fn other_opcodes_get_notimp() {
+        let mut query = query(&[("example.com", TYPE_A)]);
+        // NOTIFY
+        query.flags |= 4 << 11;
+        let reply = reply(&query);
+        assert_eq!(reply.rcode(), RCODE_NOTIMP);
+        assert!(reply.answers.is_empty());
+    }
+
+    #[test]
+    fn responses_are_not_answered() {
+        let response = query(&[("example.com", TYPE_A)]).response(RCODE_NOERROR);
+        assert!(captive_reply(&response.to_bytes().unwrap(), IP).is_none());
+
+        let mut bytes = response.to_bytes().unwrap();
+        bytes.truncate(bytes.len() - 1);
+        assert!(captive_reply(&bytes, IP).is_none());
+    }
+
+    #[test]
+    fn large_replies_are_truncated() {
+        let names: Vec<String> = (0..20).map(|i| format!("host{}.example.com", i)).collect();
+        let questions: Vec<(&str, u16)> =
+            names.iter().map(|name| (name.as_str(), TYPE_A)).collect();
+
+        let mut query = query(&questions);
+        let bytes = captive_reply(&query.to_bytes().unwrap(), IP).unwrap();
+        assert!(bytes.len() <= DNS_UDP_LEN);
+        let reply = Message::parse(&bytes).unwrap();
+        assert!(reply.is_truncated());
+        assert!(reply.answers.is_empty());
+        assert_eq!(reply.questions.len(), 20);
+
+        // Fits the buffer the client advertises.
+        query.additionals.push(opt(4096));
+        let bytes = captive_reply(&query.to_bytes().unwrap(), IP).unwrap();
+        assert!(bytes.len() > DNS_UDP_LEN);
+        let reply = Message::parse(&bytes).unwrap();
+        assert!(!reply.is_truncated());
+        assert_eq!(reply.answers.len(), 20);
+        assert_eq!(reply.additionals, [opt(4096)]);
+
+        // Too small a buffer is taken as 512.
+        query.additionals = vec![opt(100)];
+        let reply =
+            Message::parse(&captive_reply(&query.to_bytes().unwrap(), IP).unwrap()).unwrap();
+        assert!(reply.is_truncated());
+        assert_eq!(reply.additionals, [opt(100)]);
+    }
+}
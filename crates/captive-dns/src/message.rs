@@ -0,0 +1,542 @@
+//! DNS message parsing and building (RFC 1035), std only so it runs on the
+//! host too.
+
+use std::fmt;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_NS: u16 = 2;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_MX: u16 = 15;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_OPT: u16 = 41;
+pub const CLASS_IN: u16 = 1;
+
+pub const RCODE_NOERROR: u8 = 0;
+pub const RCODE_FORMERR: u8 = 1;
+pub const RCODE_SERVFAIL: u8 = 2;
+pub const RCODE_NXDOMAIN: u8 = 3;
+pub const RCODE_NOTIMP: u8 = 4;
+
+pub const OPCODE_QUERY: u8 = 0;
+
+const MAX_NAME_LEN: usize = 255;
+const MAX_LABEL_LEN: usize = 63;
+/// More pointers than this in one name can only be a loop.
+const MAX_POINTERS: usize = 16;
+
+const FLAG_QR: u16 = 0x8000;
+pub const FLAG_AA: u16 = 0x0400;
+const FLAG_TC: u16 = 0x0200;
+const FLAG_RD: u16 = 0x0100;
+const FLAG_RA: u16 = 0x0080;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsError {
+    /// The message ends in the middle of a field.
+    Truncated,
+    /// A compression pointer loops or points outside the message.
+    BadPointer,
+    LabelTooLong,
+    NameTooLong,
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsError::Truncated => write!(f, "truncated DNS message"),
+            DnsError::BadPointer => write!(f, "bad compression pointer"),
+            DnsError::LabelTooLong => write!(f, "label longer than 63 bytes"),
+            DnsError::NameTooLong => write!(f, "name longer than 255 bytes"),
+        }
+    }
+}
+
+impl std::error::Error for DnsError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+    /// Dotted, without the trailing dot. The root is empty.
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub name: String,
+    pub rtype: u16,
+    /// The UDP payload size for OPT records.
+    pub class: u16,
+    /// Extended RCODE and flags for OPT records.
+    pub ttl: u32,
+    /// Names inside the well-known types (CNAME, NS, PTR, MX, SOA, SRV) are
+    /// decompressed, so records can be copied into another message. Other
+    /// types are kept as received.
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    pub fn a(name: &str, ttl: u32, ip: [u8; 4]) -> Self {
+        Self {
+            name: name.to_string(),
+            rtype: TYPE_A,
+            class: CLASS_IN,
+            ttl,
+            data: ip.to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Message {
+    pub id: u16,
+    /// The header flags, including opcode and RCODE.
+    pub flags: u16,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    pub authorities: Vec<Record>,
+    pub additionals: Vec<Record>,
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], DnsError> {
+        let end = self.pos.checked_add(len).ok_or(DnsError::Truncated)?;
+        let bytes = self.buf.get(self.pos..end).ok_or(DnsError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, DnsError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, DnsError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads a possibly compressed name, leaving the position after it.
+    fn name(&mut self) -> Result<String, DnsError> {
+        let mut labels: Vec<String> = Vec::new();
+        let mut len = 0;
+        let mut pos = self.pos;
+        let mut end = None;
+        let mut pointers = 0;
+        loop {
+            let byte = *self.buf.get(pos).ok_or(DnsError::Truncated)?;
+            match byte & 0xC0 {
+                0x00 if byte == 0 => {
+                    self.pos = end.unwrap_or(pos + 1);
+                    return Ok(labels.join("."));
+                }
+                0x00 => {
+                    let label = self
+                        .buf
+                        .get(pos + 1..pos + 1 + byte as usize)
+                        .ok_or(DnsError::Truncated)?;
+                    len += label.len() + 1;
+                    if len > MAX_NAME_LEN {
+                        return Err(DnsError::NameTooLong);
+                    }
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    pos += 1 + label.len();
+                }
+                0xC0 => {
+                    let low = *self.buf.get(pos + 1).ok_or(DnsError::Truncated)?;
+                    pointers += 1;
+                    if pointers > MAX_POINTERS {
+                        return Err(DnsError::BadPointer);
+                    }
+                    end.get_or_insert(pos + 2);
+                    pos = (((byte & 0x3F) as usize) << 8) | low as usize;
+                    if pos >= self.buf.len() {
+                        return Err(DnsError::BadPointer);
+                    }
+                }
+                // 0x40 and 0x80 are reserved label types.
+                _ => return Err(DnsError::LabelTooLong),
+            }
+        }
+    }
+
+    fn question(&mut self) -> Result<Question, DnsError> {
+        Ok(Question {
+            name: self.name()?,
+            qtype: self.u16()?,
+            qclass: self.u16()?,
+        })
+    }
+
+    fn record(&mut self) -> Result<Record, DnsError> {
+        let name = self.name()?;
+        let rtype = self.u16()?;
+        let class = self.u16()?;
+        let ttl = self.u32()?;
+        let len = self.u16()? as usize;
+        let end = self.pos + len;
+        let data = self.rdata(rtype, len)?;
+        if self.pos != end {
+            return Err(DnsError::Truncated);
+        }
+        Ok(Record {
+            name,
+            rtype,
+            class,
+            ttl,
+            data,
+        })
+    }
+
+    /// Reads `len` bytes of RDATA, expanding compressed names.
+    fn rdata(&mut self, rtype: u16, len: usize) -> Result<Vec<u8>, DnsError> {
+        // Fixed fields before and after the names.
+        let (prefix, names, suffix) = match rtype {
+            TYPE_NS | TYPE_CNAME | TYPE_PTR => (0, 1, 0),
+            TYPE_MX => (2, 1, 0),
+            TYPE_SRV => (6, 1, 0),
+            TYPE_SOA => (0, 2, 20),
+            _ => return Ok(self.bytes(len)?.to_vec()),
+        };
+        let mut data = self.bytes(prefix)?.to_vec();
+        for _ in 0..names {
+            write_name(&mut data, &self.name()?)?;
+        }
+        data.extend_from_slice(self.bytes(suffix)?);
+        Ok(data)
+    }
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) -> Result<(), DnsError> {
+    let mut len = 1;
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        if label.len() > MAX_LABEL_LEN {
+            return Err(DnsError::LabelTooLong);
+        }
+        len += label.len() + 1;
+        if len > MAX_NAME_LEN {
+            return Err(DnsError::NameTooLong);
+        }
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+    Ok(())
+}
+
+fn write_record(out: &mut Vec<u8>, record: &Record) -> Result<(), DnsError> {
+    write_name(out, &record.name)?;
+    out.extend_from_slice(&record.rtype.to_be_bytes());
+    out.extend_from_slice(&record.class.to_be_bytes());
+    out.extend_from_slice(&record.ttl.to_be_bytes());
+    out.extend_from_slice(&(record.data.len() as u16).to_be_bytes());
+    out.extend_from_slice(&record.data);
+    Ok(())
+}
+
+impl Message {
+    pub fn parse(buf: &[u8]) -> Result<Self, DnsError> {
+        let mut reader = Reader { buf, pos: 0 };
+        let id = reader.u16()?;
+        let flags = reader.u16()?;
+        let counts = [reader.u16()?, reader.u16()?, reader.u16()?, reader.u16()?];
+
+        let mut message = Message {
+            id,
+            flags,
+            ..Default::default()
+        };
+        for _ in 0..counts[0] {
+            message.questions.push(reader.question()?);
+        }
+        for (count, section) in counts[1..].iter().zip([
+            &mut message.answers,
+            &mut message.authorities,
+            &mut message.additionals,
+        ]) {
+            for _ in 0..*count {
+                section.push(reader.record()?);
+            }
+        }
+        Ok(message)
+    }
+
+    /// Reads just the ID and flags, to answer messages that don't parse.
+    pub fn parse_header(buf: &[u8]) -> Option<Self> {
+        let mut reader = Reader { buf, pos: 0 };
+        Some(Message {
+            id: reader.u16().ok()?,
+            flags: reader.u16().ok()?,
+            ..Default::default()
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DnsError> {
+        let mut out = Vec::with_capacity(512);
+        out.extend_from_slice(&self.id.to_be_bytes());
+        out.extend_from_slice(&self.flags.to_be_bytes());
+        for count in [
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len(),
+        ] {
+            out.extend_from_slice(&(count as u16).to_be_bytes());
+        }
+        for question in &self.questions {
+            write_name(&mut out, &question.name)?;
+            out.extend_from_slice(&question.qtype.to_be_bytes());
+            out.extend_from_slice(&question.qclass.to_be_bytes());
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additionals)
+        {
+            write_record(&mut out, record)?;
+        }
+        Ok(out)
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.flags & FLAG_QR != 0
+    }
+
+    pub fn opcode(&self) -> u8 {
+        ((self.flags >> 11) & 0x0F) as u8
+    }
+
+    pub fn rcode(&self) -> u8 {
+        (self.flags & 0x0F) as u8
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.flags & FLAG_TC != 0
+    }
+
+    /// Drops the records that don't fit and flags the message as truncated,
+    /// the OPT record is kept.
+    pub fn truncate(&mut self) {
+        self.answers.clear();
+        self.authorities.clear();
+        self.additionals.retain(|record| record.rtype == TYPE_OPT);
+        self.flags |= FLAG_TC;
+    }
+
+    /// The EDNS OPT pseudo-record, if any.
+    pub fn opt(&self) -> Option<&Record> {
+        self.additionals
+            .iter()
+            .find(|record| record.rtype == TYPE_OPT)
+    }
+
+    /// An authoritative response skeleton with the same ID, opcode, RD flag
+    /// and questions. An OPT record in the query is echoed back, as EDNS
+    /// clients expect.
+    pub fn response(&self, rcode: u8) -> Message {
+        let flags = FLAG_QR
+            | FLAG_AA
+            | FLAG_RA
+            | (self.flags & FLAG_RD)
+            | ((self.opcode() as u16) << 11)
+            | (rcode & 0x0F) as u16;
+        Message {
+            id: self.id,
+            flags,
+            questions: self.questions.clone(),
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: self.opt().cloned().into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question(name: &str, qtype: u16) -> Question {
+        Question {
+            name: name.to_string(),
+            qtype,
+            qclass: CLASS_IN,
+        }
+    }
+
+    /// A query header with RD set and the given counts, without a body.
+    fn header(id: u16, counts: [u16; 4]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&id.to_be_bytes());
+        out.extend_from_slice(&FLAG_RD.to_be_bytes());
+        for count in counts {
+            out.extend_from_slice(&count.to_be_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn multi_question_round_trip() {
+        let query = Message {
+            id: 0x1234,
+            flags: FLAG_RD,
+            questions: vec![question("example.com", TYPE_A), question("Example.ORG", 28)],
+            ..Default::default()
+        };
+        let parsed = Message::parse(&query.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed, query);
+        assert!(!parsed.is_response());
+        assert_eq!(parsed.opcode(), OPCODE_QUERY);
+    }
+
+    #[test]
+    fn root_name_is_empty() {
+        let mut buf = header(1, [1, 0, 0, 0]);
+        buf.extend_from_slice(&[0, 0, 2, 0, 1]);
+        let parsed = Message::parse(&buf).unwrap();
+        assert_eq!(parsed.questions, [question("", TYPE_NS)]);
+    }
+
+    #[test]
+    fn compressed_names_are_expanded() {
+        let mut buf = header(1, [1, 1, 0, 0]);
+        // Question `example.com` at offset 12.
+        buf.extend_from_slice(b"\x07example\x03com\x00\x00\x05\x00\x01");
+        // Answer `www.example.com` CNAME `example.com`, both compressed.
+        buf.extend_from_slice(b"\x03www\xc0\x0c\x00\x05\x00\x01\x00\x00\x01\x2c\x00\x02\xc0\x0c");
+        let parsed = Message::parse(&buf).unwrap();
+        let answer = &parsed.answers[0];
+        assert_eq!(answer.name, "www.example.com");
+        assert_eq!(answer.ttl, 300);
+        assert_eq!(answer.data, b"\x07example\x03com\x00");
+    }
+
+    #[test]
+    fn pointer_loop_is_rejected() {
+        let mut buf = header(1, [1, 0, 0, 0]);
+        // A pointer to itself.
+        buf.extend_from_slice(&[0xC0, 12, 0, 1, 0, 1]);
+        assert_eq!(Message::parse(&buf), Err(DnsError::BadPointer));
+
+        // Two pointers to each other.
+        let mut buf = header(1, [1, 0, 0, 0]);
+        buf.extend_from_slice(&[0xC0, 14, 0xC0, 12, 0, 1, 0, 1]);
+        assert_eq!(Message::parse(&buf), Err(DnsError::BadPointer));
+    }
+
+    #[test]
+    fn pointer_outside_the_message_is_rejected() {
+        let mut buf = header(1, [1, 0, 0, 0]);
+        buf.extend_from_slice(&[0xC0, 0xFF, 0, 1, 0, 1]);
+        assert_eq!(Message::parse(&buf), Err(DnsError::BadPointer));
+    }
+
+    #[test]
+    fn reserved_label_types_are_rejected() {
+        let mut buf = header(1, [1, 0, 0, 0]);
+        buf.extend_from_slice(&[0x40, 0, 0, 1, 0, 1]);
+        assert_eq!(Message::parse(&buf), Err(DnsError::LabelTooLong));
+    }
+
+    #[test]
+    fn truncated_input_is_rejected() {
+        let query = Message {
+            id: 7,
+            questions: vec![question("example.com", TYPE_A)],
+            additionals: vec![Record::a("example.com", 60, [10, 0, 0, 1])],
+            ..Default::default()
+        };
+        let bytes = query.to_bytes().unwrap();
+        for len in 0..bytes.len() {
+            assert_eq!(
+                Message::parse(&bytes[..len]),
+                Err(DnsError::Truncated),
+                "parsed {} of {} bytes",
+                len,
+                bytes.len()
+            );
+        }
+        assert!(Message::parse_header(&bytes[..3]).is_none());
+        assert_eq!(Message::parse_header(&bytes[..4]).unwrap().id, 7);
+    }
+
+    #[test]
+    fn rdata_length_has_to_match() {
+        let mut buf = header(1, [0, 1, 0, 0]);
+        // A CNAME whose RDLENGTH claims one byte more than the name.
+        buf.extend_from_slice(b"\x00\x00\x05\x00\x01\x00\x00\x00\x3c\x00\x04\x01a\x00\xff");
+        assert_eq!(Message::parse(&buf), Err(DnsError::Truncated));
+    }
+
+    #[test]
+    fn long_names_are_rejected() {
+        let label = "a".repeat(64);
+        let query = Message {
+            questions: vec![question(&label, TYPE_A)],
+            ..Default::default()
+        };
+        assert_eq!(query.to_bytes(), Err(DnsError::LabelTooLong));
+
+        let name = vec!["a".repeat(63); 4].join(".");
+        let query = Message {
+            questions: vec![question(&name, TYPE_A)],
+            ..Default::default()
+        };
+        assert_eq!(query.to_bytes(), Err(DnsError::NameTooLong));
+    }
+
+    #[test]
+    fn response_echoes_id_rd_and_opt() {
+        let opt = Record {
+            name: String::new(),
+            rtype: TYPE_OPT,
+            class: 4096,
+            ttl: 0,
+            data: Vec::new(),
+        };
+        let query = Message {
+            id: 0xBEEF,
+            flags: FLAG_RD,
+            questions: vec![question("example.com", TYPE_A)],
+            additionals: vec![opt.clone()],
+            ..Default::default()
+        };
+        let response = query.response(RCODE_NXDOMAIN);
+        assert_eq!(response.id, 0xBEEF);
+        assert!(response.is_response());
+        assert_eq!(response.flags & FLAG_RD, FLAG_RD);
+        assert_eq!(response.rcode(), RCODE_NXDOMAIN);
+        assert_eq!(response.questions, query.questions);
+        assert_eq!(response.additionals, [opt]);
+        assert_eq!(response.opt().unwrap().class, 4096);
+    }
+
+    #[test]
+    fn truncate_keeps_opt_and_sets_tc() {
+        let opt = Record {
+            name: String::new(),
+            rtype: TYPE_OPT,
+            class: 1232,
+            ttl: 0,
+            data: Vec::new(),
+        };
+        let mut response = Message {
+            answers: vec![Record::a("example.com", 60, [10, 0, 0, 1])],
+            authorities: vec![Record::a("example.com", 60, [10, 0, 0, 2])],
+            additionals: vec![Record::a("example.com", 60, [10, 0, 0, 3]), opt.clone()],
+            ..Default::default()
+        };
+        response.truncate();
+        assert!(response.is_truncated());
+        assert!(response.answers.is_empty());
+        assert!(response.authorities.is_empty());
+        assert_eq!(response.additionals, [opt]);
+    }
+}
@@ -0,0 +1,207 @@
+//! The forwarder between loopback sockets standing in for a client, the
+//! server socket and an upstream resolver.
+
+use captive_dns::{
+    message::{Message, Question, Record, CLASS_IN, RCODE_NOERROR, TYPE_A, TYPE_OPT},
+    Forwarder,
+};
+use std::{
+    io::ErrorKind,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+const IP: Ipv4Addr = Ipv4Addr::new(192, 168, 4, 1);
+const LOCAL_NAME: &str = "byr-pet.local";
+
+fn socket() -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(300)))
+        .unwrap();
+    socket
+}
+
+struct Setup {
+    server: UdpSocket,
+    upstream: UdpSocket,
+    forwarder: Forwarder,
+}
+
+impl Setup {
+    fn new() -> Self {
+        let upstream = socket();
+        let forwarder = Forwarder::new(IP, vec![Ipv4Addr::LOCALHOST], vec![LOCAL_NAME.to_string()])
+            .with_port(upstream.local_addr().unwrap().port());
+        Self {
+            server: socket(),
+            upstream,
+            forwarder,
+        }
+    }
+
+    /// Sends `message` from `from` to the server and lets the forwarder
+    /// handle it.
+    fn send(&mut self, from: &UdpSocket, message: &Message) {
+        let server = self.server.local_addr().unwrap();
+        from.send_to(&message.to_bytes().unwrap(), server).unwrap();
+        let mut buf = [0u8; 2048];
+        let (len, addr) = self.server.recv_from(&mut buf).unwrap();
+        self.forwarder.handle(&self.server, &buf[..len], addr);
+    }
+
+    /// The next query the upstream resolver got, `None` if there was none.
+    fn upstream_query(&self) -> Option<Message> {
+        receive(&self.upstream).map(|(message, _)| message)
+    }
+
+    /// Answers `query` from the upstream resolver with `ip`.
+    fn answer(&mut self, query: &Message, ip: [u8; 4]) {
+        let mut response = query.response(RCODE_NOERROR);
+        response
+            .answers
+            .push(Record::a(&query.questions[0].name, 300, ip));
+        let upstream = self.upstream.try_clone().unwrap();
+        self.send(&upstream, &response);
+    }
+}
+
+fn receive(socket: &UdpSocket) -> Option<(Message, SocketAddr)> {
+    let mut buf = [0u8; 2048];
+    match socket.recv_from(&mut buf) {
+        Ok((len, addr)) => Some((Message::parse(&buf[..len]).unwrap(), addr)),
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => None,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn query(id: u16, name: &str) -> Message {
+    Message {
+        id,
+        flags: 0x0100,
+        questions: vec![Question {
+            name: name.to_string(),
+            qtype: TYPE_A,
+            qclass: CLASS_IN,
+        }],
+        ..Default::default()
+    }
+}
+
+fn answer_ip(message: &Message) -> [u8; 4] {
+    message.answers[0].data.as_slice().try_into().unwrap()
+}
+
+#[test]
+fn ids_are_remapped_per_client() {
+    let mut setup = Setup::new();
+    let (alice, bob) = (socket(), socket());
+
+    // Both clients happen to use the same ID.
+    setup.send(&alice, &query(0x1111, "a.example.com"));
+    setup.send(&bob, &query(0x1111, "b.example.com"));
+    let first = setup.upstream_query().unwrap();
+    let second = setup.upstream_query().unwrap();
+    assert_ne!(first.id, second.id);
+    assert_eq!(first.questions, query(0, "a.example.com").questions);
+
+    // Answered out of order.
+    setup.answer(&second, [10, 0, 0, 2]);
+    setup.answer(&first, [10, 0, 0, 1]);
+
+    let (reply, _) = receive(&alice).unwrap();
+    assert_eq!(reply.id, 0x1111);
+    assert_eq!(reply.questions[0].name, "a.example.com");
+    assert_eq!(answer_ip(&reply), [10, 0, 0, 1]);
+
+    let (reply, _) = receive(&bob).unwrap();
+    assert_eq!(reply.id, 0x1111);
+    assert_eq!(answer_ip(&reply), [10, 0, 0, 2]);
+}
+
+#[test]
+fn answers_are_served_from_the_cache() {
+    let mut setup = Setup::new();
+    let client = socket();
+
+    setup.send(&client, &query(1, "example.com"));
+    let upstream_query = setup.upstream_query().unwrap();
+    setup.answer(&upstream_query, [10, 0, 0, 1]);
+    receive(&client).unwrap();
+
+    setup.send(&client, &query(2, "EXAMPLE.com"));
+    assert!(setup.upstream_query().is_none());
+    let (reply, _) = receive(&client).unwrap();
+    assert_eq!(reply.id, 2);
+    assert_eq!(reply.questions[0].name, "EXAMPLE.com");
+    assert_eq!(answer_ip(&reply), [10, 0, 0, 1]);
+    assert!(reply.answers[0].ttl <= 300);
+}
+
+#[test]
+fn local_names_are_answered_locally() {
+    let mut setup = Setup::new();
+    let client = socket();
+
+    setup.send(&client, &query(7, LOCAL_NAME));
+    assert!(setup.upstream_query().is_none());
+    let (reply, _) = receive(&client).unwrap();
+    assert_eq!(reply.id, 7);
+    assert_eq!(answer_ip(&reply), IP.octets());
+}
+
+#[test]
+fn unexpected_answers_are_dropped() {
+    let mut setup = Setup::new();
+    let client = socket();
+
+    setup.send(&client, &query(1, "example.com"));
+    let mut upstream_query = setup.upstream_query().unwrap();
+    upstream_query.id = upstream_query.id.wrapping_add(1);
+    setup.answer(&upstream_query, [10, 0, 0, 1]);
+    assert!(receive(&client).is_none());
+}
+
+#[test]
+fn late_answers_are_dropped() {
+    let mut setup = Setup::new();
+    let client = socket();
+
+    setup.send(&client, &query(1, "example.com"));
+    let upstream_query = setup.upstream_query().unwrap();
+    setup
+        .forwarder
+        .expire(Instant::now() + Duration::from_secs(10));
+    setup.answer(&upstream_query, [10, 0, 0, 1]);
+    assert!(receive(&client).is_none());
+}
+
+#[test]
+fn edns_buffer_is_clamped_upstream() {
+    let mut setup = Setup::new();
+    let client = socket();
+
+    let mut query = query(1, "example.com");
+    query.additionals.push(Record {
+        name: String::new(),
+        rtype: TYPE_OPT,
+        class: 4096,
+        ttl: 0,
+        data: Vec::new(),
+    });
+    setup.send(&client, &query);
+    let upstream_query = setup.upstream_query().unwrap();
+    assert_eq!(upstream_query.opt().unwrap().class, 1232);
+}
+
+#[test]
+fn only_upstream_servers_may_answer() {
+    let setup = Setup::new();
+    let upstream = setup.upstream.local_addr().unwrap();
+    assert!(setup.forwarder.is_upstream(upstream));
+    assert!(!setup.forwarder.is_upstream(socket().local_addr().unwrap()));
+    assert!(!setup.forwarder.is_upstream(SocketAddr::new(
+        Ipv4Addr::new(10, 0, 0, 1).into(),
+        upstream.port()
+    )));
+}
@@ -0,0 +1,182 @@
+import { useState, useRef } from "preact/hooks"
+import RebootButton from "./Reboot"
+
+const inputClassName = "block w-full appearance-none rounded-md border border-gray-300 px-3 py-2 placeholder-gray-400 shadow-sm focus:border-indigo-500 focus:outline-none focus:ring-indigo-500 dark:border-gray-700 dark:bg-gray-800 dark:text-gray-50 dark:placeholder-gray-500"
+
+export interface Session {
+    /** `admin` once the device is online, `provisioning` before. */
+    mode: string
+    authenticated: boolean
+    /** How the provisioning AP is protected: `generated`, `open` or `custom`. */
+    ap_security: string
+}
+
+const AP_SECURITY = {
+    generated: '随机密码 (见串口日志)',
+    custom: '自定义密码',
+    open: '无密码',
+}
+
+async function post(path: string, params: Record<string, string>) {
+    const response = await fetch(path, {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/x-www-form-urlencoded'
+        },
+        body: new URLSearchParams(params).toString().replace(/\+/g, '%20')
+    })
+    return response.json()
+}
+
+/**
+ * Asks for the admin PIN, which opens a session for the other settings.
+ */
+export default function AdminLogin({ onLogin }: { onLogin: () => void }) {
+    const pinRef = useRef(null)
+    const [errorMsg, setErrorMsg] = useState('')
+
+    async function login(e) {
+        e.preventDefault()
+        try {
+            const result = await post('/session', { pin: pinRef.current.value })
+            if (result.code) {
+                setErrorMsg(result.message)
+            } else {
+                setErrorMsg('')
+                onLogin()
+            }
+        } catch (error) {
+            console.error(error)
+            setErrorMsg('登录失败: ' + error.message)
+        }
+    }
+
+    return (
+        <div className="flex min-h-full flex-col justify-center py-12 sm:px-6 lg:px-8">
+            <div className="sm:mx-auto sm:w-full sm:max-w-md">
+                <h2 className="mt-6 text-center text-2xl font-bold tracking-tight text-gray-900 dark:text-gray-50">
+                    输入管理员 PIN
+                </h2>
+                <p className="mt-2 text-center text-sm text-gray-600 dark:text-gray-400">
+                    PIN 在首次启动时随机生成，会在启动时打印到串口日志
+                </p>
+            </div>
+            <form className="mt-8 space-y-6 px-4 sm:mx-auto sm:w-full sm:max-w-md" onSubmit={login}>
+                <input
+                    className={inputClassName}
+                    type="password"
+                    inputMode="numeric"
+                    autoComplete="current-password"
+                    placeholder="PIN"
+                    required
+                    ref={pinRef}
+                />
+                <button
+                    type="submit"
+                    className="flex w-full justify-center rounded-md border border-transparent bg-indigo-600 px-4 py-2 text-sm font-medium text-white shadow-sm hover:bg-indigo-700 focus:outline-none focus:ring-2 focus:ring-indigo-500 focus:ring-offset-2"
+                >
+                    继续
+                </button>
+                {errorMsg && <div className="text-sm text-red-500 dark:text-red-400">{errorMsg}</div>}
+            </form>
+        </div>
+    )
+}
+
+/**
+ * The admin PIN and the password of the provisioning AP.
+ */
+export function SecuritySettings({ session, onChange }: { session: Session, onChange: () => void }) {
+    const [security, setSecurity] = useState(session.ap_security)
+    const passphraseRef = useRef(null)
+    const pinRef = useRef(null)
+    const [errorMsg, setErrorMsg] = useState('')
+    const [saved, setSaved] = useState('')
+    const [needsReboot, setNeedsReboot] = useState(false)
+
+    async function save(path: string, params: Record<string, string>, message: string, reboot = false) {
+        try {
+            const result = await post(path, params)
+            if (result.code) {
+                setErrorMsg(result.message)
+                setSaved('')
+            } else {
+                setErrorMsg('')
+                setSaved(message)
+                setNeedsReboot(reboot)
+                onChange()
+            }
+        } catch (error) {
+            console.error(error)
+            setErrorMsg('设置失败: ' + error.message)
+        }
+    }
+
+    function saveSecurity(e) {
+        e.preventDefault()
+        const params: Record<string, string> = { security }
+        if (security === 'custom') {
+            params.passphrase = passphraseRef.current?.value ?? ''
+        }
+        save('/ap', params, '热点密码已保存，重启后生效', true)
+    }
+
+    function savePin(e) {
+        e.preventDefault()
+        save('/pin', { pin: pinRef.current.value }, 'PIN 已修改，请重新登录')
+    }
+
+    return (
+        <div className="flex flex-col items-center py-4 text-sm text-gray-600 dark:text-gray-400">
+            <details className="w-full max-w-md px-4">
+                <summary className="cursor-pointer">安全设置</summary>
+                <div className="mt-4 space-y-4">
+                    <label className="block">
+                        配网热点:{' '}
+                        <select
+                            className="rounded-md border border-gray-300 bg-transparent px-2 py-1 dark:border-gray-700"
+                            value={security}
+                            onChange={e => setSecurity((e.target as HTMLSelectElement).value)}
+                        >
+                            {Object.entries(AP_SECURITY).map(([value, label]) => (
+                                <option key={value} value={value}>{label}</option>
+                            ))}
+                        </select>
+                    </label>
+                    {security === 'custom' && (
+                        <input
+                            className={inputClassName}
+                            type="password"
+                            autoComplete="new-password"
+                            placeholder="热点密码 (8-63 位)"
+                            ref={passphraseRef}
+                        />
+                    )}
+                    <button
+                        className="text-indigo-600 hover:text-indigo-500 dark:text-indigo-400"
+                        onClick={saveSecurity}
+                    >
+                        保存热点设置
+                    </button>
+                    <input
+                        className={inputClassName}
+                        type="password"
+                        inputMode="numeric"
+                        autoComplete="new-password"
+                        placeholder="新的管理员 PIN (4-12 位数字)"
+                        ref={pinRef}
+                    />
+                    <button
+                        className="text-indigo-600 hover:text-indigo-500 dark:text-indigo-400"
+                        onClick={savePin}
+                    >
+                        修改 PIN
+                    </button>
+                    {saved && <div>{saved}</div>}
+                    {saved && needsReboot && <RebootButton />}
+                    {errorMsg && <div className="text-red-500 dark:text-red-400">{errorMsg}</div>}
+                </div>
+            </details>
+        </div>
+    )
+}
@@ -0,0 +1,43 @@
+const inputClassName = "block w-full appearance-none rounded-md border border-gray-300 px-3 py-2 placeholder-gray-400 shadow-sm focus:border-indigo-500 focus:outline-none focus:ring-indigo-500 dark:border-gray-700 dark:bg-gray-800 dark:text-gray-50 dark:placeholder-gray-500"
+const labelClassName = "block text-sm font-medium text-gray-700 dark:text-gray-300"
+
+const FIELDS = [
+    { name: 'ip_address', label: 'IP 地址', placeholder: '留空则使用 DHCP' },
+    { name: 'ip_mask', label: '子网掩码', placeholder: '255.255.255.0' },
+    { name: 'ip_gateway', label: '网关', placeholder: '192.168.1.1' },
+    { name: 'ip_dns', label: 'DNS 服务器', placeholder: '192.168.1.1' },
+    { name: 'hostname', label: '主机名', placeholder: 'byr-pet' },
+    { name: 'mac', label: '克隆 MAC 地址', placeholder: '02:00:00:00:00:01' },
+    { name: 'priority', label: '优先级', placeholder: '0-255，越大越优先，默认 0' },
+]
+
+const BUPT_FIELDS = FIELDS.filter(field => field.name === 'mac' || field.name === 'priority')
+
+/**
+ * Optional static IPv4 settings, a MAC to clone and the priority among the
+ * saved networks, collected into `values` as form fields. The campus portal
+ * doesn't take IP settings.
+ */
+export default function AdvancedSettings({ values, macOnly = false }: { values: { current: Record<string, string> }, macOnly?: boolean }) {
+    return (
+        <details className="text-sm text-gray-700 dark:text-gray-300">
+            <summary className="cursor-pointer">高级设置</summary>
+            <div className="mt-4 space-y-4">
+                {(macOnly ? BUPT_FIELDS : FIELDS).map(field => (
+                    <div key={field.name}>
+                        <label htmlFor={field.name} className={labelClassName}>{field.label}</label>
+                        <div className="mt-1">
+                            <input
+                                id={field.name}
+                                className={inputClassName}
+                                type="text"
+                                placeholder={field.placeholder}
+                                onInput={e => values.current[field.name] = (e.target as HTMLInputElement).value}
+                            />
+                        </div>
+                    </div>
+                ))}
+            </div>
+        </details>
+    )
+}
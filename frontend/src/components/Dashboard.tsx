@@ -0,0 +1,241 @@
+import { useState, useEffect } from "preact/hooks"
+
+const STATES: Record<string, string> = {
+    idle: '空闲',
+    scanning: '扫描中',
+    associating: '正在连接',
+    dhcp_wait: '正在获取 IP',
+    portal_check: '检查认证状态',
+    portal_login: '正在登录 BUPT-portal',
+    online: '在线',
+    backoff: '等待重试',
+}
+
+const TYPES: Record<string, string> = {
+    bupt_portal: 'BUPT-portal',
+    normal_wifi: 'Wi-Fi',
+    enterprise: '企业网络',
+}
+
+interface SavedNetwork {
+    ssid: string
+    type: string
+    priority: number
+}
+
+interface PortalSession {
+    online: boolean
+    account: string | null
+    ip: string | null
+    used_traffic: string | null
+    used_time: string | null
+    balance: string | null
+}
+
+interface Portal {
+    status: PortalSession
+    /** Logged out from this page, automatic logins are held back. */
+    paused: boolean
+}
+
+interface Status {
+    version: string
+    hostname: string
+    uptime: number
+    state: string
+    /** Uptime when `state` was entered. */
+    state_since: number
+    connection: {
+        ssid: string | null
+        ip: string | null
+        rssi: number | null
+        portal: boolean | null
+        error: string | null
+    }
+    saved: SavedNetwork[]
+    hotspot: boolean
+}
+
+function formatUptime(seconds: number) {
+    const days = Math.floor(seconds / 86400)
+    const hours = Math.floor(seconds % 86400 / 3600)
+    const minutes = Math.floor(seconds % 3600 / 60)
+    return days ? `${days} 天 ${hours} 小时` : `${hours} 小时 ${minutes} 分钟`
+}
+
+/**
+ * Connection state, saved networks and logs, once the device is online.
+ */
+export default function Dashboard() {
+    const [status, setStatus] = useState<Status | null>(null)
+    const [logs, setLogs] = useState<string[]>([])
+    const [portal, setPortal] = useState<Portal | null>(null)
+    const [errorMsg, setErrorMsg] = useState('')
+
+    function load() {
+        fetch('/api/admin/status')
+            .then(response => response.json())
+            .then(setStatus)
+            .catch(console.error)
+    }
+
+    function loadLogs() {
+        fetch('/api/admin/logs')
+            .then(response => response.json())
+            .then(setLogs)
+            .catch(console.error)
+    }
+
+    function loadPortal() {
+        fetch('/api/admin/portal')
+            .then(response => response.json())
+            .then(result => {
+                if (result.code) {
+                    setErrorMsg(result.message)
+                } else {
+                    setPortal(result)
+                }
+            })
+            .catch(console.error)
+    }
+
+    async function portalAction(action: 'login' | 'logout') {
+        try {
+            const response = await fetch(`/api/admin/portal/${action}`, { method: 'POST' })
+            const result = await response.json()
+            if (result.code) {
+                setErrorMsg(result.message)
+            } else {
+                setErrorMsg('')
+            }
+        } catch (error) {
+            console.error(error)
+            setErrorMsg('操作失败: ' + error.message)
+        } finally {
+            // A login happens on the next check, give it a moment.
+            setTimeout(loadPortal, action === 'login' ? 5000 : 0)
+        }
+    }
+
+    const onPortal = status?.connection.portal != null
+    useEffect(() => {
+        if (onPortal) {
+            loadPortal()
+        }
+    }, [onPortal])
+
+    useEffect(() => {
+        load()
+        const timer = setInterval(load, 5000)
+        return () => clearInterval(timer)
+    }, [])
+
+    async function forget(ssid: string) {
+        if (!confirm(`确定删除 ${ssid}？`)) {
+            return
+        }
+        try {
+            const response = await fetch(`/api/admin/networks?ssid=${encodeURIComponent(ssid)}`, {
+                method: 'DELETE'
+            })
+            const result = await response.json()
+            if (result.code) {
+                setErrorMsg(result.message)
+            } else {
+                setErrorMsg('')
+                load()
+            }
+        } catch (error) {
+            console.error(error)
+            setErrorMsg('删除失败: ' + error.message)
+        }
+    }
+
+    if (!status) {
+        return null
+    }
+
+    const { connection } = status
+    return (
+        <div className="flex flex-col items-center py-8 text-sm text-gray-600 dark:text-gray-400">
+            <div className="w-full max-w-md space-y-6 px-4">
+                <div>
+                    <h1 className="text-2xl font-bold tracking-tight text-gray-900 dark:text-gray-50">
+                        BYR-pet
+                    </h1>
+                    <p>{status.hostname} · v{status.version} · 已运行 {formatUptime(status.uptime)}</p>
+                </div>
+                <table className="w-full text-left">
+                    <tbody>
+                        <tr><th>状态</th><td>{STATES[status.state] ?? status.state} · {formatUptime(status.uptime - status.state_since)}</td></tr>
+                        <tr><th>网络</th><td>{connection.ssid ?? '未连接'}</td></tr>
+                        <tr><th>IP</th><td>{connection.ip ?? '-'}</td></tr>
+                        <tr><th>信号</th><td>{connection.rssi !== null ? `${connection.rssi} dBm` : '-'}</td></tr>
+                        {connection.portal !== null && (
+                            <tr><th>校园网认证</th><td>{connection.portal ? '已登录' : '未登录'}</td></tr>
+                        )}
+                        {status.hotspot && <tr><th>共享网络</th><td>已开启</td></tr>}
+                    </tbody>
+                </table>
+                {portal && (
+                    <div>
+                        <h2 className="text-lg font-bold text-gray-900 dark:text-gray-50">校园网账号</h2>
+                        {portal.status.online ? (
+                            <table className="mt-2 w-full text-left">
+                                <tbody>
+                                    <tr><th>账号</th><td>{portal.status.account ?? '-'}</td></tr>
+                                    <tr><th>IP</th><td>{portal.status.ip ?? '-'}</td></tr>
+                                    <tr><th>已用流量</th><td>{portal.status.used_traffic ?? '-'}</td></tr>
+                                    <tr><th>已用时长</th><td>{portal.status.used_time ?? '-'}</td></tr>
+                                    <tr><th>余额</th><td>{portal.status.balance ?? '-'}</td></tr>
+                                </tbody>
+                            </table>
+                        ) : (
+                            <p className="mt-2">{portal.paused ? '已注销，不会自动重新登录' : '未登录'}</p>
+                        )}
+                        <button
+                            className="mt-2 text-indigo-600 hover:text-indigo-500 dark:text-indigo-400"
+                            onClick={() => portalAction(portal.status.online ? 'logout' : 'login')}
+                        >
+                            {portal.status.online ? '注销' : '登录'}
+                        </button>
+                    </div>
+                )}
+                {connection.error && (
+                    <div className="text-red-500 dark:text-red-400">上次检查失败: {connection.error}</div>
+                )}
+                <div>
+                    <h2 className="text-lg font-bold text-gray-900 dark:text-gray-50">已保存的网络</h2>
+                    <ul className="mt-2 space-y-1">
+                        {status.saved.map(network => (
+                            <li key={network.ssid} className="flex justify-between">
+                                <span>
+                                    {network.ssid} ({TYPES[network.type] ?? network.type}, 优先级 {network.priority})
+                                </span>
+                                <button
+                                    className="text-red-500 hover:text-red-400"
+                                    onClick={() => forget(network.ssid)}
+                                >
+                                    删除
+                                </button>
+                            </li>
+                        ))}
+                    </ul>
+                    {errorMsg && <div className="mt-2 text-red-500 dark:text-red-400">{errorMsg}</div>}
+                </div>
+                <details onToggle={e => (e.target as HTMLDetailsElement).open && loadLogs()}>
+                    <summary className="cursor-pointer">日志</summary>
+                    <button
+                        className="mt-2 text-indigo-600 hover:text-indigo-500 dark:text-indigo-400"
+                        onClick={loadLogs}
+                    >
+                        刷新
+                    </button>
+                    <pre className="mt-2 max-h-96 overflow-auto whitespace-pre-wrap break-all text-xs">
+                        {logs.join('\n')}
+                    </pre>
+                </details>
+            </div>
+        </div>
+    )
+}
@@ -0,0 +1,157 @@
+import Loading from "../assets/loading.svg"
+import AdvancedSettings from "./AdvancedSettings"
+import { useState, useRef } from "preact/hooks"
+
+const inputClassName = "block w-full appearance-none rounded-md border border-gray-300 px-3 py-2 placeholder-gray-400 shadow-sm focus:border-indigo-500 focus:outline-none focus:ring-indigo-500 dark:border-gray-700 dark:bg-gray-800 dark:text-gray-50 dark:placeholder-gray-500"
+const labelClassName = "block text-sm font-medium text-gray-700 dark:text-gray-300"
+
+export default function Component({ switching = false }: { switching?: boolean }) {
+    const [loading, setLoading] = useState(false)
+    const ssidRef = useRef(null)
+    const identityRef = useRef(null)
+    const anonymousIdentityRef = useRef(null)
+    const passwordRef = useRef(null)
+    const eapMethodRef = useRef(null)
+    const caCertRef = useRef(null)
+    const advancedRef = useRef<Record<string, string>>({})
+    const [errorMsg, setErrorMsg] = useState('')
+    const [connected, setConnected] = useState(false)
+
+    async function submit(e) {
+        e.preventDefault()
+        setLoading(true)
+        try {
+            const body = new URLSearchParams({
+                ssid: ssidRef.current.value,
+                identity: identityRef.current.value,
+                anonymous_identity: anonymousIdentityRef.current.value,
+                password: passwordRef.current.value,
+                eap_method: eapMethodRef.current.value,
+                ca_cert: caCertRef.current.value,
+                ...advancedRef.current,
+            })
+            const response = await fetch('/enterprise', {
+                method: 'POST',
+                headers: {
+                    'Content-Type': 'application/x-www-form-urlencoded'
+                },
+                body: body.toString().replace(/\+/g, '%20')
+            })
+            const result = await response.json()
+            if (result.code) {
+                setErrorMsg(result.message)
+            } else {
+                setConnected(true)
+            }
+        } catch (error) {
+            console.error(error)
+            setErrorMsg('连接失败: ' + error.message)
+        } finally {
+            setLoading(false)
+        }
+    }
+
+    if (connected) {
+        return (
+            <div className="min-h-[75vh] flex flex-col items-center justify-center px-4 py-12">
+                <div className="w-full max-w-md space-y-8">
+                    <div className="text-center">
+                        <svg
+                            className="mx-auto h-24 w-24 text-green-500"
+                            fill="none"
+                            stroke="currentColor"
+                            viewBox="0 0 24 24"
+                            xmlns="http://www.w3.org/2000/svg"
+                        >
+                            <path
+                                strokeLinecap="round"
+                                strokeLinejoin="round"
+                                strokeWidth="2"
+                                d="M5 13l4 4L19 7"
+                            ></path>
+                        </svg>
+                        <h1 className="text-2xl font-bold tracking-tight text-gray-900 dark:text-gray-50">
+                            {switching ? '已保存' : '连接成功'}
+                        </h1>
+                        <p className="mt-2 text-xs text-gray-600 dark:text-gray-400">
+                            {switching ? '设备正在切换到新网络，页面可能会暂时断开' : '现在您可以断开 BYR-pet Wi-Fi 连接'}
+                        </p>
+                    </div>
+                </div>
+            </div>
+        )
+    }
+
+    return (
+        <>
+            <div className="min-h-[75vh] flex flex-col items-center justify-center px-4 py-12">
+                <div className="w-full max-w-md space-y-8">
+                    <div className="text-center">
+                        <h1 className="text-3xl font-bold tracking-tight text-gray-900 dark:text-gray-50">
+                            Welcome to BYR-pet
+                        </h1>
+                        <p className="mt-2 text-sm text-gray-600 dark:text-gray-400">
+                            连接 eduroam 等 802.1X 企业网络
+                        </p>
+                    </div>
+                    <div className="space-y-6">
+                        {errorMsg && (<div className="text-red-500 dark:text-red-400 text-center text-sm mb-4">
+                            {errorMsg}
+                        </div>)}
+                        <div>
+                            <label htmlFor="enterprise-ssid" className={labelClassName}>Wi-Fi 名称</label>
+                            <div className="mt-1">
+                                <input id="enterprise-ssid" required={true} className={inputClassName} type="text" defaultValue="eduroam" ref={ssidRef} />
+                            </div>
+                        </div>
+                        <div>
+                            <label htmlFor="identity" className={labelClassName}>身份</label>
+                            <div className="mt-1">
+                                <input id="identity" autoComplete="username" required={true} className={inputClassName} type="text" placeholder="student@bupt.edu.cn" ref={identityRef} />
+                            </div>
+                        </div>
+                        <div>
+                            <label htmlFor="anonymous-identity" className={labelClassName}>匿名身份 (可选)</label>
+                            <div className="mt-1">
+                                <input id="anonymous-identity" className={inputClassName} type="text" placeholder="anonymous@bupt.edu.cn" ref={anonymousIdentityRef} />
+                            </div>
+                        </div>
+                        <div>
+                            <label htmlFor="enterprise-password" className={labelClassName}>密码</label>
+                            <div className="mt-1">
+                                <input id="enterprise-password" autoComplete="current-password" required={true} className={inputClassName} type="password" ref={passwordRef} />
+                            </div>
+                        </div>
+                        <div>
+                            <label htmlFor="eap-method" className={labelClassName}>EAP 方法</label>
+                            <div className="mt-1">
+                                <select id="eap-method" className={inputClassName} ref={eapMethodRef}>
+                                    <option value="peap">PEAP (MSCHAPv2)</option>
+                                    <option value="ttls">TTLS (MSCHAPv2)</option>
+                                </select>
+                            </div>
+                        </div>
+                        <div>
+                            <label htmlFor="ca-cert" className={labelClassName}>CA 证书 (可选, PEM)</label>
+                            <div className="mt-1">
+                                <textarea id="ca-cert" rows={3} className={inputClassName} placeholder="-----BEGIN CERTIFICATE-----" ref={caCertRef} />
+                            </div>
+                        </div>
+                        <AdvancedSettings values={advancedRef} />
+                        <div>
+                            <button
+                                type="submit"
+                                disabled={loading}
+                                onClick={submit}
+                                className="flex w-full justify-center rounded-md border border-transparent bg-indigo-600 py-2 px-4 text-sm font-medium text-white shadow-sm hover:bg-indigo-700 focus:outline-none focus:ring-2 focus:ring-indigo-500 focus:ring-offset-2 dark:bg-indigo-500 dark:hover:bg-indigo-600 dark:focus:ring-indigo-600 disabled:opacity-50 disabled:cursor-not-allowed"
+                            >
+                                {loading && <img src={Loading} className="w-5 h-5 mr-2 animate-spin" alt="loading" />}
+                                {loading ? "连接中..." : errorMsg ? "重试" : "连接企业网络"}
+                            </button>
+                        </div>
+                    </div>
+                </div>
+            </div>
+        </>
+    )
+}
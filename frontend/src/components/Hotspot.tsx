@@ -0,0 +1,128 @@
+import { useState, useEffect, useRef } from "preact/hooks"
+import RebootButton from "./Reboot"
+
+const inputClassName = "block w-full appearance-none rounded-md border border-gray-300 px-3 py-2 placeholder-gray-400 shadow-sm focus:border-indigo-500 focus:outline-none focus:ring-indigo-500 dark:border-gray-700 dark:bg-gray-800 dark:text-gray-50 dark:placeholder-gray-500"
+
+interface Client {
+    mac: string
+    ip: string | null
+    rssi: number
+}
+
+export interface HotspotStatus {
+    enabled: boolean
+    password_set: boolean
+    /** Served by the running hotspot rather than the provisioning portal. */
+    active: boolean
+    clients: Client[]
+}
+
+/**
+ * Repeater settings, and the connected devices while the hotspot is running.
+ */
+export default function Hotspot({ status, onChange }: { status: HotspotStatus, onChange: () => void }) {
+    const [enabled, setEnabled] = useState(status.enabled)
+    const passwordRef = useRef(null)
+    const [errorMsg, setErrorMsg] = useState('')
+    const [saved, setSaved] = useState(false)
+
+    useEffect(() => {
+        if (!status.active) {
+            return
+        }
+        const timer = setInterval(onChange, 5000)
+        return () => clearInterval(timer)
+    }, [status.active])
+
+    async function save(e) {
+        e.preventDefault()
+        try {
+            const body = new URLSearchParams({
+                enabled: String(enabled),
+                password: passwordRef.current?.value ?? '',
+            })
+            const response = await fetch('/hotspot', {
+                method: 'POST',
+                headers: {
+                    'Content-Type': 'application/x-www-form-urlencoded'
+                },
+                body: body.toString().replace(/\+/g, '%20')
+            })
+            const result = await response.json()
+            if (result.code) {
+                setErrorMsg(result.message)
+                setSaved(false)
+            } else {
+                setErrorMsg('')
+                setSaved(true)
+                onChange()
+            }
+        } catch (error) {
+            console.error(error)
+            setErrorMsg('设置失败: ' + error.message)
+        }
+    }
+
+    return (
+        <div className="flex flex-col items-center py-4 text-sm text-gray-600 dark:text-gray-400">
+            <div className="w-full max-w-md space-y-4 px-4">
+                {status.active && (
+                    <div>
+                        <h2 className="text-lg font-bold text-gray-900 dark:text-gray-50">
+                            已连接的设备 ({status.clients.length})
+                        </h2>
+                        <table className="mt-2 w-full text-left">
+                            <thead>
+                                <tr>
+                                    <th>MAC</th>
+                                    <th>IP</th>
+                                    <th>信号</th>
+                                </tr>
+                            </thead>
+                            <tbody>
+                                {status.clients.map(client => (
+                                    <tr key={client.mac}>
+                                        <td className="font-mono">{client.mac}</td>
+                                        <td>{client.ip ?? '-'}</td>
+                                        <td>{client.rssi} dBm</td>
+                                    </tr>
+                                ))}
+                            </tbody>
+                        </table>
+                    </div>
+                )}
+                <details open={status.active}>
+                    <summary className="cursor-pointer">共享网络 (中继模式)</summary>
+                    <div className="mt-4 space-y-4">
+                        <label className="flex items-center gap-2">
+                            <input
+                                type="checkbox"
+                                checked={enabled}
+                                onChange={e => setEnabled((e.target as HTMLInputElement).checked)}
+                            />
+                            连接成功后保持 BYR-pet 热点，供其他设备上网
+                        </label>
+                        {enabled && (
+                            <input
+                                className={inputClassName}
+                                type="password"
+                                autoComplete="new-password"
+                                placeholder={status.password_set ? '留空则不修改热点密码' : '热点密码 (8-63 位)'}
+                                ref={passwordRef}
+                            />
+                        )}
+                        <button
+                            className="text-indigo-600 hover:text-indigo-500 dark:text-indigo-400"
+                            onClick={save}
+                        >
+                            保存
+                        </button>
+                        {saved && <div>已保存，{status.active ? '重启后生效' : '连接成功后生效'}</div>}
+                        {saved && status.active && <RebootButton />}
+                        {errorMsg && <div className="text-red-500 dark:text-red-400">{errorMsg}</div>}
+                    </div>
+                </details>
+            </div>
+        </div>
+    )
+}
@@ -1,10 +1,29 @@
 import Loading from "../assets/loading.svg"
+import AdvancedSettings from "./AdvancedSettings"
 import { useState, useRef } from "preact/hooks"
 
-export default function Component() {
+const ERROR_MESSAGES: Record<string, string> = {
+    wrong_credentials: '学号或密码错误',
+    account_suspended: '账号已欠费或停用',
+    too_many_devices: '在线设备数已达上限，请先下线其他设备',
+    portal_unreachable: '无法连接 BUPT-portal，请确认设备在信号范围内',
+    unexpected_status: 'BUPT-portal 返回了异常响应',
+    missing_location: 'BUPT-portal 返回了异常响应',
+    unexpected_redirect: 'BUPT-portal 返回了异常跳转',
+    missing_cookie: 'BUPT-portal 未返回会话，请重试',
+    redirect_loop: '网关重定向出现循环，请稍后重试',
+    too_many_redirects: '网关重定向次数过多，请稍后重试',
+}
+
+/**
+ * `switching`: on the admin page, where the device saves the network and
+ * switches to it after answering.
+ */
+export default function Component({ switching = false }: { switching?: boolean }) {
     const [loading, setLoading] = useState(false)
     const usernameRef = useRef(null)
     const passwordRef = useRef(null)
+    const advancedRef = useRef<Record<string, string>>({})
     const [errorMsg, setErrorMsg] = useState('')
     const [loggedIn, setLoggedIn] = useState(false)
 
@@ -12,16 +31,21 @@ export default function Component() {
         e.preventDefault()
         setLoading(true)
         try {
+            const body = new URLSearchParams({
+                username: usernameRef.current.value,
+                password: passwordRef.current.value,
+                ...advancedRef.current,
+            })
             const response = await fetch('/login', {
                 method: 'POST',
                 headers: {
                     'Content-Type': 'application/x-www-form-urlencoded'
                 },
-                body: `username=${encodeURIComponent(usernameRef.current.value)}&password=${encodeURIComponent(passwordRef.current.value)}`
+                body: body.toString().replace(/\+/g, '%20')
             })
             const result = await response.json()
             if (result.code) {
-                setErrorMsg(result.message)
+                setErrorMsg(ERROR_MESSAGES[result.error] ?? result.message)
             } else {
                 setLoggedIn(true)
             }
@@ -53,10 +77,10 @@ export default function Component() {
                             ></path>
                         </svg>
                         <h1 className="text-2xl font-bold tracking-tight text-gray-900 dark:text-gray-50">
-                            登录成功
+                            {switching ? '已保存' : '登录成功'}
                         </h1>
                         <p className="mt-2 text-xs text-gray-600 dark:text-gray-400">
-                            现在您可以断开 BYR-pet Wi-Fi 连接
+                            {switching ? '设备正在切换到新网络，页面可能会暂时断开' : '现在您可以断开 BYR-pet Wi-Fi 连接'}
                         </p>
                     </div>
                 </div>
@@ -118,6 +142,7 @@ export default function Component() {
                                 />
                             </div>
                         </div>
+                        <AdvancedSettings values={advancedRef} macOnly={true} />
                         <div>
                             <button
                                 type="submit"
@@ -0,0 +1,66 @@
+import { useState, useEffect } from "preact/hooks"
+
+const POLICIES = {
+    factory: '出厂 MAC',
+    random_once: '随机 MAC (固定)',
+    per_network: '每个网络不同的随机 MAC',
+    every_boot: '每次启动随机 MAC',
+}
+
+export default function MacPolicy() {
+    const [policy, setPolicy] = useState('')
+    const [errorMsg, setErrorMsg] = useState('')
+
+    useEffect(() => {
+        fetch('/api/mac')
+            .then(response => response.json())
+            .then(result => setPolicy(result.policy))
+            .catch(console.error)
+    }, [])
+
+    async function change(e) {
+        const value = (e.target as HTMLSelectElement).value
+        try {
+            const response = await fetch('/mac', {
+                method: 'POST',
+                headers: {
+                    'Content-Type': 'application/x-www-form-urlencoded'
+                },
+                body: `policy=${encodeURIComponent(value)}`
+            })
+            const result = await response.json()
+            if (result.code) {
+                setErrorMsg(result.message)
+            } else {
+                setErrorMsg('')
+                setPolicy(value)
+            }
+        } catch (error) {
+            console.error(error)
+            setErrorMsg('设置失败: ' + error.message)
+        }
+    }
+
+    if (!policy) {
+        return null
+    }
+
+    return (
+        <div className="flex flex-col items-center py-4 text-sm text-gray-600 dark:text-gray-400">
+            <label htmlFor="mac-policy">
+                MAC 地址:{' '}
+                <select
+                    id="mac-policy"
+                    className="rounded-md border border-gray-300 bg-transparent px-2 py-1 dark:border-gray-700"
+                    value={policy}
+                    onChange={change}
+                >
+                    {Object.entries(POLICIES).map(([value, label]) => (
+                        <option key={value} value={value}>{label}</option>
+                    ))}
+                </select>
+            </label>
+            {errorMsg && <div className="text-red-500 dark:text-red-400 mt-2">{errorMsg}</div>}
+        </div>
+    )
+}
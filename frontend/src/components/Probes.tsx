@@ -0,0 +1,77 @@
+import { useState } from "preact/hooks"
+
+const inputClassName = "block w-full appearance-none rounded-md border border-gray-300 px-3 py-2 font-mono text-xs shadow-sm focus:border-indigo-500 focus:outline-none focus:ring-indigo-500 dark:border-gray-700 dark:bg-gray-800 dark:text-gray-50"
+
+/**
+ * The connectivity probes used to tell whether the portal intercepts, edited
+ * as the JSON the device stores.
+ */
+export default function Probes() {
+    const [config, setConfig] = useState('')
+    const [errorMsg, setErrorMsg] = useState('')
+    const [saved, setSaved] = useState(false)
+
+    function load() {
+        fetch('/api/admin/probes')
+            .then(response => response.json())
+            .then(config => setConfig(JSON.stringify(config, null, 2)))
+            .catch(console.error)
+    }
+
+    async function send(method: 'POST' | 'DELETE') {
+        try {
+            const response = await fetch('/api/admin/probes', {
+                method,
+                headers: { 'Content-Type': 'application/json' },
+                body: method === 'POST' ? config : undefined,
+            })
+            const result = await response.json()
+            if (result.code) {
+                setErrorMsg(result.message)
+                setSaved(false)
+            } else {
+                setErrorMsg('')
+                setSaved(true)
+                load()
+            }
+        } catch (error) {
+            console.error(error)
+            setErrorMsg('设置失败: ' + error.message)
+        }
+    }
+
+    return (
+        <div className="flex flex-col items-center py-4 text-sm text-gray-600 dark:text-gray-400">
+            <details
+                className="w-full max-w-md px-4"
+                onToggle={e => (e.target as HTMLDetailsElement).open && load()}
+            >
+                <summary className="cursor-pointer">连通性检测</summary>
+                <div className="mt-4 space-y-4">
+                    <textarea
+                        className={inputClassName}
+                        rows={12}
+                        value={config}
+                        onInput={e => setConfig((e.target as HTMLTextAreaElement).value)}
+                    />
+                    <div className="flex gap-4">
+                        <button
+                            className="text-indigo-600 hover:text-indigo-500 dark:text-indigo-400"
+                            onClick={() => send('POST')}
+                        >
+                            保存
+                        </button>
+                        <button
+                            className="text-indigo-600 hover:text-indigo-500 dark:text-indigo-400"
+                            onClick={() => send('DELETE')}
+                        >
+                            恢复默认
+                        </button>
+                    </div>
+                    {saved && <div>已保存，下次检查时生效</div>}
+                    {errorMsg && <div className="text-red-500 dark:text-red-400">{errorMsg}</div>}
+                </div>
+            </details>
+        </div>
+    )
+}
@@ -0,0 +1,39 @@
+import { useState } from "preact/hooks"
+
+/** How long the device takes to come back, after which the page reloads. */
+const REBOOT_TIME = 8000
+
+/**
+ * Restarts the device, for the settings that only apply at boot.
+ */
+export default function RebootButton() {
+    const [message, setMessage] = useState('')
+
+    async function reboot() {
+        try {
+            const response = await fetch('/reboot', { method: 'POST' })
+            const result = await response.json()
+            if (result.code) {
+                setMessage(result.message)
+            } else {
+                setMessage('正在重启，请稍候…')
+                setTimeout(() => location.reload(), REBOOT_TIME)
+            }
+        } catch (error) {
+            console.error(error)
+            setMessage('重启失败: ' + error.message)
+        }
+    }
+
+    return (
+        <div>
+            <button
+                className="text-indigo-600 hover:text-indigo-500 dark:text-indigo-400"
+                onClick={reboot}
+            >
+                立即重启
+            </button>
+            {message && <div>{message}</div>}
+        </div>
+    )
+}
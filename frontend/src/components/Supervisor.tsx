@@ -0,0 +1,101 @@
+import { useState } from "preact/hooks"
+
+const inputClassName = "block w-full appearance-none rounded-md border border-gray-300 px-3 py-2 placeholder-gray-400 shadow-sm focus:border-indigo-500 focus:outline-none focus:ring-indigo-500 dark:border-gray-700 dark:bg-gray-800 dark:text-gray-50 dark:placeholder-gray-500"
+
+/** Response of `GET /api/admin/supervisor`, in seconds. */
+interface Options {
+    check_interval: number
+    min_backoff: number
+    max_backoff: number
+}
+
+const FIELDS: [keyof Options, string][] = [
+    ['check_interval', '检查间隔 (秒)'],
+    ['min_backoff', '最短重试间隔 (秒)'],
+    ['max_backoff', '最长重试间隔 (秒)'],
+]
+
+/**
+ * How often the device checks its connection, and how long it waits before
+ * retrying after a failure.
+ */
+export default function Supervisor() {
+    const [options, setOptions] = useState<Options | null>(null)
+    const [errorMsg, setErrorMsg] = useState('')
+    const [saved, setSaved] = useState(false)
+
+    function load() {
+        fetch('/api/admin/supervisor')
+            .then(response => response.json())
+            .then(setOptions)
+            .catch(console.error)
+    }
+
+    async function send(method: 'POST' | 'DELETE') {
+        try {
+            const response = await fetch('/api/admin/supervisor', {
+                method,
+                headers: { 'Content-Type': 'application/json' },
+                body: method === 'POST' ? JSON.stringify(options) : undefined,
+            })
+            const result = await response.json()
+            if (result.code) {
+                setErrorMsg(result.message)
+                setSaved(false)
+            } else {
+                setErrorMsg('')
+                setSaved(true)
+                load()
+            }
+        } catch (error) {
+            console.error(error)
+            setErrorMsg('设置失败: ' + error.message)
+        }
+    }
+
+    return (
+        <div className="flex flex-col items-center py-4 text-sm text-gray-600 dark:text-gray-400">
+            <details
+                className="w-full max-w-md px-4"
+                onToggle={e => (e.target as HTMLDetailsElement).open && load()}
+            >
+                <summary className="cursor-pointer">检查与重试</summary>
+                {options && (
+                    <div className="mt-4 space-y-4">
+                        {FIELDS.map(([key, label]) => (
+                            <label key={key} className="block">
+                                {label}
+                                <input
+                                    className={inputClassName}
+                                    type="number"
+                                    min={1}
+                                    value={options[key]}
+                                    onInput={e => setOptions({
+                                        ...options,
+                                        [key]: Number((e.target as HTMLInputElement).value),
+                                    })}
+                                />
+                            </label>
+                        ))}
+                        <div className="flex gap-4">
+                            <button
+                                className="text-indigo-600 hover:text-indigo-500 dark:text-indigo-400"
+                                onClick={() => send('POST')}
+                            >
+                                保存
+                            </button>
+                            <button
+                                className="text-indigo-600 hover:text-indigo-500 dark:text-indigo-400"
+                                onClick={() => send('DELETE')}
+                            >
+                                恢复默认
+                            </button>
+                        </div>
+                        {saved && <div>已保存，立即生效</div>}
+                        {errorMsg && <div className="text-red-500 dark:text-red-400">{errorMsg}</div>}
+                    </div>
+                )}
+            </details>
+        </div>
+    )
+}
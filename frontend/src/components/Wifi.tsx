@@ -0,0 +1,199 @@
+import Loading from "../assets/loading.svg"
+import AdvancedSettings from "./AdvancedSettings"
+import { useState, useRef, useEffect } from "preact/hooks"
+
+interface Network {
+    ssid: string
+    rssi: number
+    channel: number
+    auth_method: string | null
+    campus: boolean
+}
+
+export default function Component({ switching = false }: { switching?: boolean }) {
+    const [loading, setLoading] = useState(false)
+    const ssidRef = useRef(null)
+    const passwordRef = useRef(null)
+    const authMethodRef = useRef(null)
+    const advancedRef = useRef<Record<string, string>>({})
+    const [errorMsg, setErrorMsg] = useState('')
+    const [connected, setConnected] = useState(false)
+    const [networks, setNetworks] = useState<Network[]>([])
+
+    // The admin server has no scan, the station is busy with the network.
+    useEffect(() => {
+        if (switching) {
+            return
+        }
+        fetch('/api/scan')
+            .then(response => response.ok ? response.json() : [])
+            .then(setNetworks)
+            .catch(console.error)
+    }, [])
+
+    async function submit(e) {
+        e.preventDefault()
+        setLoading(true)
+        try {
+            const body = new URLSearchParams({
+                ssid: ssidRef.current.value,
+                password: passwordRef.current.value,
+                auth_method: authMethodRef.current.value,
+                ...advancedRef.current,
+            })
+            const response = await fetch('/wifi', {
+                method: 'POST',
+                headers: {
+                    'Content-Type': 'application/x-www-form-urlencoded'
+                },
+                body: body.toString().replace(/\+/g, '%20')
+            })
+            const result = await response.json()
+            if (result.code) {
+                setErrorMsg(result.message)
+            } else {
+                setConnected(true)
+            }
+        } catch (error) {
+            console.error(error)
+            setErrorMsg('连接失败: ' + error.message)
+        } finally {
+            setLoading(false)
+        }
+    }
+
+    if (connected) {
+        return (
+            <div className="min-h-[75vh] flex flex-col items-center justify-center px-4 py-12">
+                <div className="w-full max-w-md space-y-8">
+                    <div className="text-center">
+                        <svg
+                            className="mx-auto h-24 w-24 text-green-500"
+                            fill="none"
+                            stroke="currentColor"
+                            viewBox="0 0 24 24"
+                            xmlns="http://www.w3.org/2000/svg"
+                        >
+                            <path
+                                strokeLinecap="round"
+                                strokeLinejoin="round"
+                                strokeWidth="2"
+                                d="M5 13l4 4L19 7"
+                            ></path>
+                        </svg>
+                        <h1 className="text-2xl font-bold tracking-tight text-gray-900 dark:text-gray-50">
+                            {switching ? '已保存' : '连接成功'}
+                        </h1>
+                        <p className="mt-2 text-xs text-gray-600 dark:text-gray-400">
+                            {switching ? '设备正在切换到新网络，页面可能会暂时断开' : '现在您可以断开 BYR-pet Wi-Fi 连接'}
+                        </p>
+                    </div>
+                </div>
+            </div>
+        )
+    }
+
+    return (
+        <>
+            <div className="min-h-[75vh] flex flex-col items-center justify-center px-4 py-12">
+                <div className="w-full max-w-md space-y-8">
+                    <div className="text-center">
+                        <h1 className="text-3xl font-bold tracking-tight text-gray-900 dark:text-gray-50">
+                            Welcome to BYR-pet
+                        </h1>
+                        <p className="mt-2 text-sm text-gray-600 dark:text-gray-400">
+                            输入 Wi-Fi 名称和密码以连接其他网络
+                        </p>
+                    </div>
+                    <div className="space-y-6">
+                        <div>
+                            {errorMsg && (<div className="text-red-500 dark:text-red-400 text-center text-sm mb-4">
+                                {errorMsg}
+                            </div>)}
+                            <label
+                                htmlFor="ssid"
+                                className="block text-sm font-medium text-gray-700 dark:text-gray-300"
+                            >
+                                Wi-Fi 名称
+                            </label>
+                            <div className="mt-1">
+                                <input
+                                    id="ssid"
+                                    required={true}
+                                    className="block w-full appearance-none rounded-md border border-gray-300 px-3 py-2 placeholder-gray-400 shadow-sm focus:border-indigo-500 focus:outline-none focus:ring-indigo-500 dark:border-gray-700 dark:bg-gray-800 dark:text-gray-50 dark:placeholder-gray-500"
+                                    type="text"
+                                    name="ssid"
+                                    list={switching ? undefined : "networks"}
+                                    ref={ssidRef}
+                                />
+                                <datalist id="networks">
+                                    {networks.filter(network => !network.campus).map(network => (
+                                        <option key={network.ssid} value={network.ssid}>
+                                            {`${network.rssi} dBm · ${network.auth_method ?? 'unknown'}`}
+                                        </option>
+                                    ))}
+                                </datalist>
+                            </div>
+                        </div>
+                        <div>
+                            <label
+                                htmlFor="wifi-password"
+                                className="block text-sm font-medium text-gray-700 dark:text-gray-300"
+                            >
+                                密码
+                            </label>
+                            <div className="mt-1">
+                                <input
+                                    id="wifi-password"
+                                    autoComplete="current-password"
+                                    className="block w-full appearance-none rounded-md border border-gray-300 px-3 py-2 placeholder-gray-400 shadow-sm focus:border-indigo-500 focus:outline-none focus:ring-indigo-500 dark:border-gray-700 dark:bg-gray-800 dark:text-gray-50 dark:placeholder-gray-500"
+                                    type="password"
+                                    name="password"
+                                    ref={passwordRef}
+                                />
+                            </div>
+                        </div>
+                        <div>
+                            <label
+                                htmlFor="auth-method"
+                                className="block text-sm font-medium text-gray-700 dark:text-gray-300"
+                            >
+                                认证方式
+                            </label>
+                            <div className="mt-1">
+                                <select
+                                    id="auth-method"
+                                    className="block w-full appearance-none rounded-md border border-gray-300 px-3 py-2 shadow-sm focus:border-indigo-500 focus:outline-none focus:ring-indigo-500 dark:border-gray-700 dark:bg-gray-800 dark:text-gray-50"
+                                    name="auth_method"
+                                    ref={authMethodRef}
+                                >
+                                    <option value="auto">自动检测</option>
+                                    <option value="open">无密码</option>
+                                    <option value="wep">WEP</option>
+                                    <option value="wpa">WPA</option>
+                                    <option value="wpa/wpa2">WPA/WPA2</option>
+                                    <option value="wpa2">WPA2</option>
+                                    <option value="wpa2/wpa3">WPA2/WPA3</option>
+                                    <option value="wpa3">WPA3</option>
+                                    <option value="wapi">WAPI</option>
+                                </select>
+                            </div>
+                        </div>
+                        <AdvancedSettings values={advancedRef} />
+                        <div>
+                            <button
+                                type="submit"
+                                disabled={loading}
+                                onClick={submit}
+                                className="flex w-full justify-center rounded-md border border-transparent bg-indigo-600 py-2 px-4 text-sm font-medium text-white shadow-sm hover:bg-indigo-700 focus:outline-none focus:ring-2 focus:ring-indigo-500 focus:ring-offset-2 dark:bg-indigo-500 dark:hover:bg-indigo-600 dark:focus:ring-indigo-600 disabled:opacity-50 disabled:cursor-not-allowed"
+                            >
+                                {loading && <img src={Loading} className="w-5 h-5 mr-2 animate-spin" alt="loading" />}
+                                {loading ? "连接中..." : errorMsg ? "重试" : "连接 Wi-Fi"}
+                            </button>
+                        </div>
+                    </div>
+                </div>
+            </div>
+        </>
+    )
+}
@@ -1,10 +1,103 @@
 import { render } from 'preact';
+import { useState, useEffect } from 'preact/hooks';
 import Login from './components/Login';
+import Wifi from './components/Wifi';
+import Enterprise from './components/Enterprise';
+import MacPolicy from './components/MacPolicy';
+import Hotspot, { HotspotStatus } from './components/Hotspot';
+import AdminLogin, { SecuritySettings, Session } from './components/Admin';
+import Dashboard from './components/Dashboard';
+import Probes from './components/Probes';
+import Supervisor from './components/Supervisor';
 import './style.css';
 
+const MODES = {
+	bupt: '连接 BUPT-portal',
+	wifi: '连接其他 Wi-Fi',
+	enterprise: '连接企业网络',
+};
+
+type Mode = keyof typeof MODES;
+
 export function App() {
+	const [mode, setMode] = useState<Mode>('bupt');
+	const [hotspot, setHotspot] = useState<HotspotStatus | null>(null);
+	const [session, setSession] = useState<Session | null>(null);
+
+	function loadSession() {
+		fetch('/api/session')
+			.then(response => response.json())
+			.then(setSession)
+			.catch(console.error);
+	}
+
+	function loadHotspot() {
+		fetch('/api/hotspot')
+			.then(response => response.ok ? response.json() : null)
+			.then(setHotspot)
+			.catch(console.error);
+	}
+
+	useEffect(loadSession, []);
+	// The hotspot settings are only shown to an admin.
+	useEffect(() => {
+		if (session?.authenticated) {
+			loadHotspot();
+		}
+	}, [session?.authenticated]);
+
+	if (!session) {
+		return null;
+	}
+	if (!session.authenticated) {
+		return <AdminLogin onLogin={loadSession} />;
+	}
+
+	const admin = session.mode === 'admin';
+	const forms = (
+		<>
+			{mode === 'bupt' && <Login switching={admin} />}
+			{mode === 'wifi' && <Wifi switching={admin} />}
+			{mode === 'enterprise' && <Enterprise switching={admin} />}
+			<div className="flex justify-center gap-4 text-sm">
+				{(Object.keys(MODES) as Mode[])
+					.filter(other => other !== mode)
+					.map(other => (
+						<button
+							key={other}
+							className="text-indigo-600 hover:text-indigo-500 dark:text-indigo-400"
+							onClick={() => setMode(other)}
+						>
+							{MODES[other]}
+						</button>
+					))}
+			</div>
+		</>
+	);
+
+	if (admin) {
+		return (
+			<>
+				<Dashboard />
+				<details className="mx-auto w-full max-w-md px-4 text-sm text-gray-600 dark:text-gray-400">
+					<summary className="cursor-pointer">修改网络</summary>
+					{forms}
+				</details>
+				{hotspot && <Hotspot status={hotspot} onChange={loadHotspot} />}
+				<Probes />
+				<Supervisor />
+				<SecuritySettings session={session} onChange={loadSession} />
+			</>
+		);
+	}
+
 	return (
-		<Login />
+		<>
+			{forms}
+			<MacPolicy />
+			{hotspot && <Hotspot status={hotspot} onChange={loadHotspot} />}
+			<SecuritySettings session={session} onChange={loadSession} />
+		</>
 	);
 }
 
@@ -0,0 +1,59 @@
+//! Keeps the latest log lines in memory, for the admin page. Only the Rust
+//! side is recorded, the C components log to the console alone.
+
+use esp_idf_svc::{log::EspLogger, sys};
+use log::{Log, Metadata, Record};
+use std::{collections::VecDeque, sync::Mutex};
+
+/// Lines kept, the oldest are dropped first.
+const CAPACITY: usize = 128;
+
+static ESP_LOGGER: EspLogger = EspLogger::new();
+static LOGGER: Logger = Logger;
+static LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Logs to the console like [`EspLogger`] and records every line.
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        ESP_LOGGER.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        ESP_LOGGER.log(record);
+
+        let millis = unsafe { sys::esp_timer_get_time() } / 1000;
+        let line = format!(
+            "({}) {} {}: {}",
+            millis,
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        let mut lines = LINES.lock().unwrap();
+        if lines.len() >= CAPACITY {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    fn flush(&self) {
+        ESP_LOGGER.flush();
+    }
+}
+
+/// Replaces [`EspLogger::initialize_default`].
+pub fn init() {
+    log::set_logger(&LOGGER)
+        .map(|()| ESP_LOGGER.initialize())
+        .unwrap();
+}
+
+/// The recorded lines, oldest first.
+pub fn lines() -> Vec<String> {
+    LINES.lock().unwrap().iter().cloned().collect()
+}
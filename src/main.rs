@@ -1,11 +1,21 @@
+mod logs;
 mod net;
 mod nvs;
 
 fn main() -> anyhow::Result<()> {
     esp_idf_svc::sys::link_patches();
-    esp_idf_svc::log::EspLogger::initialize_default();
+    logs::init();
 
-    net::connect()?.stop()?;
+    // Joining runs on an executor with the `embassy` feature, the supervisor
+    // keeps its own thread either way.
+    #[cfg(feature = "embassy")]
+    let connection = {
+        // async-io wakes its reactor through an eventfd.
+        esp_idf_svc::io::vfs::initialize_eventfd(5)?;
+        async_io::block_on(net::asynch::connect())?
+    };
+    #[cfg(not(feature = "embassy"))]
+    let connection = net::connect()?;
 
-    Ok(())
+    net::keep_alive(connection)
 }
@@ -0,0 +1,398 @@
+//! The web page of the device once it is online, served on the station side
+//! as well as to the clients of the hotspot. Shows the connection, the saved
+//! networks and the logs, and takes new credentials, which the supervisor
+//! switches to without resetting anything.
+
+use std::{
+    collections::HashMap,
+    net::Ipv4Addr,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Result;
+use embedded_svc::http::Headers;
+use esp_idf_svc::{
+    http::{server::EspHttpServer, Method},
+    io::Write,
+    sys::{self, esp},
+    wifi::EspWifi,
+};
+use serde_json::json;
+
+use super::{
+    ap,
+    auth::{self, check_admin},
+    bupt, captive, forms, hotspot, saved,
+    state::{self, NetState, Waker},
+    supervisor,
+    web::{self, parse_form, query_param, read_body_to_string},
+    NetConfig,
+};
+
+const STACK_SIZE: usize = 10240;
+/// The routes here and the shared pages need more than the default of 32.
+const MAX_URI_HANDLERS: usize = 48;
+
+/// What the supervisor found on its last check.
+#[derive(serde::Serialize, Clone, Default)]
+struct Connection {
+    ssid: Option<String>,
+    ip: Option<Ipv4Addr>,
+    rssi: Option<i8>,
+    /// Whether the BUPT-portal session was alive on the last check that got
+    /// an answer, `None` on other networks.
+    portal: Option<bool>,
+    /// Why the last check failed.
+    error: Option<String>,
+}
+
+struct Shared {
+    connection: Mutex<Connection>,
+    /// A network saved from the page, for the supervisor to switch to.
+    request: Mutex<Option<saved::SavedNetwork>>,
+    waker: Waker,
+    /// Uptime when the current state was entered.
+    state_since: AtomicI64,
+}
+
+/// Response of `GET /api/admin/status`.
+#[derive(serde::Serialize)]
+struct Status {
+    version: &'static str,
+    hostname: String,
+    /// Seconds since boot.
+    uptime: i64,
+    state: NetState,
+    /// Uptime when `state` was entered.
+    state_since: i64,
+    connection: Connection,
+    saved: Vec<saved::Summary>,
+    hotspot: bool,
+}
+
+pub struct Admin {
+    shared: Arc<Shared>,
+    #[allow(dead_code)]
+    http: EspHttpServer<'static>,
+}
+
+impl Admin {
+    /// Starts the server. `waker` interrupts the supervisor when new
+    /// credentials arrive, `hotspot` tells whether the hotspot is running.
+    pub fn start(waker: Waker, hotspot: bool) -> Result<Self> {
+        let shared = Arc::new(Shared {
+            connection: Mutex::new(Connection::default()),
+            request: Mutex::new(None),
+            waker,
+            state_since: AtomicI64::new(uptime()),
+        });
+
+        let shared1 = Arc::clone(&shared);
+        state::subscribe(move |_, _| shared1.state_since.store(uptime(), Ordering::Relaxed));
+
+        let mut http = EspHttpServer::new(&esp_idf_svc::http::server::Configuration {
+            stack_size: STACK_SIZE,
+            uri_match_wildcard: true,
+            max_uri_handlers: MAX_URI_HANDLERS,
+            ..Default::default()
+        })?;
+
+        auth::register(&mut http, "admin")?;
+
+        let shared1 = Arc::clone(&shared);
+        http.fn_handler::<anyhow::Error, _>("/api/admin/status", Method::Get, move |req| {
+            if let Some(req) = check_admin(req)? {
+                let status = Status {
+                    version: env!("CARGO_PKG_VERSION"),
+                    hostname: format!("{}.local", ap::hostname()?),
+                    uptime: uptime(),
+                    state: state::current(),
+                    state_since: shared1.state_since.load(Ordering::Relaxed),
+                    connection: shared1.connection.lock().unwrap().clone(),
+                    saved: saved::load_all()?
+                        .iter()
+                        .map(saved::Summary::from)
+                        .collect(),
+                    hotspot,
+                };
+                req.into_response(200, None, &[("Content-Type", "application/json")])?
+                    .write_all(serde_json::to_string(&status)?.as_bytes())?;
+            }
+            Ok(())
+        })?;
+
+        http.fn_handler::<anyhow::Error, _>("/api/admin/logs", Method::Get, |req| {
+            if let Some(req) = check_admin(req)? {
+                req.into_response(200, None, &[("Content-Type", "application/json")])?
+                    .write_all(serde_json::to_string(&crate::logs::lines())?.as_bytes())?;
+            }
+            Ok(())
+        })?;
+
+        http.fn_handler::<anyhow::Error, _>("/api/admin/portal", Method::Get, |req| {
+            if let Some(req) = check_admin(req)? {
+                let response = match bupt::status() {
+                    Ok(status) => json!({"code": 0, "status": status, "paused": bupt::is_paused()}),
+                    Err(e) => json!({"code": 1, "message": e.to_string()}),
+                };
+                req.into_response(200, None, &[("Content-Type", "application/json")])?
+                    .write_all(response.to_string().as_bytes())?;
+            }
+            Ok(())
+        })?;
+
+        // A manual logout holds back automatic logins until the page asks
+        // to log in again.
+        http.fn_handler::<anyhow::Error, _>("/api/admin/portal/logout", Method::Post, |req| {
+            if let Some(req) = check_admin(req)? {
+                bupt::set_paused(true);
+                let response = match bupt::logout() {
+                    Ok(_) => {
+                        bupt::set_online(false);
+                        json!({"code": 0})
+                    }
+                    Err(e) => json!({"code": 1, "message": e.to_string()}),
+                };
+                req.into_ok_response()?
+                    .write_all(response.to_string().as_bytes())?;
+            }
+            Ok(())
+        })?;
+
+        let shared1 = Arc::clone(&shared);
+        http.fn_handler::<anyhow::Error, _>("/api/admin/portal/login", Method::Post, move |req| {
+            if let Some(req) = check_admin(req)? {
+                bupt::set_paused(false);
+                shared1.waker.wake();
+                req.into_ok_response()?
+                    .write_all(json!({"code": 0}).to_string().as_bytes())?;
+            }
+            Ok(())
+        })?;
+
+        http.fn_handler::<anyhow::Error, _>("/api/admin/probes", Method::Get, |req| {
+            if let Some(req) = check_admin(req)? {
+                req.into_response(200, None, &[("Content-Type", "application/json")])?
+                    .write_all(serde_json::to_string(&bupt::probe_config()?)?.as_bytes())?;
+            }
+            Ok(())
+        })?;
+
+        http.fn_handler::<anyhow::Error, _>("/api/admin/probes", Method::Post, |req| {
+            if let Some(mut req) = check_admin(req)? {
+                let body = read_body_to_string(&mut req)?;
+                let result = serde_json::from_str(&body)
+                    .map_err(|e| anyhow::anyhow!("JSON 格式错误: {}", e))
+                    .and_then(|config| bupt::set_probe_config(Some(config)));
+                let response = match result {
+                    Ok(_) => json!({"code": 0}),
+                    Err(e) => json!({"code": 1, "message": e.to_string()}),
+                };
+                req.into_ok_response()?
+                    .write_all(response.to_string().as_bytes())?;
+            }
+            Ok(())
+        })?;
+
+        http.fn_handler::<anyhow::Error, _>("/api/admin/probes", Method::Delete, |req| {
+            if let Some(req) = check_admin(req)? {
+                let response = match bupt::set_probe_config(None) {
+                    Ok(_) => json!({"code": 0}),
+                    Err(e) => json!({"code": 1, "message": e.to_string()}),
+                };
+                req.into_ok_response()?
+                    .write_all(response.to_string().as_bytes())?;
+            }
+            Ok(())
+        })?;
+
+        http.fn_handler::<anyhow::Error, _>("/api/admin/supervisor", Method::Get, |req| {
+            if let Some(req) = check_admin(req)? {
+                req.into_response(200, None, &[("Content-Type", "application/json")])?
+                    .write_all(serde_json::to_string(&supervisor::options()?)?.as_bytes())?;
+            }
+            Ok(())
+        })?;
+
+        // The supervisor may be waiting out a long backoff, it is woken to
+        // apply the new options.
+        let shared1 = Arc::clone(&shared);
+        http.fn_handler::<anyhow::Error, _>("/api/admin/supervisor", Method::Post, move |req| {
+            if let Some(mut req) = check_admin(req)? {
+                let body = read_body_to_string(&mut req)?;
+                let result = serde_json::from_str(&body)
+                    .map_err(|e| anyhow::anyhow!("JSON 格式错误: {}", e))
+                    .and_then(|options| supervisor::set_options(Some(options)));
+                let response = match result {
+                    Ok(_) => {
+                        shared1.waker.wake();
+                        json!({"code": 0})
+                    }
+                    Err(e) => json!({"code": 1, "message": e.to_string()}),
+                };
+                req.into_ok_response()?
+                    .write_all(response.to_string().as_bytes())?;
+            }
+            Ok(())
+        })?;
+
+        let shared1 = Arc::clone(&shared);
+        http.fn_handler::<anyhow::Error, _>("/api/admin/supervisor", Method::Delete, move |req| {
+            if let Some(req) = check_admin(req)? {
+                let response = match supervisor::set_options(None) {
+                    Ok(_) => {
+                        shared1.waker.wake();
+                        json!({"code": 0})
+                    }
+                    Err(e) => json!({"code": 1, "message": e.to_string()}),
+                };
+                req.into_ok_response()?
+                    .write_all(response.to_string().as_bytes())?;
+            }
+            Ok(())
+        })?;
+
+        http.fn_handler::<anyhow::Error, _>("/api/admin/networks", Method::Delete, |req| {
+            if let Some(req) = check_admin(req)? {
+                let result = match query_param(req.uri(), "ssid") {
+                    Some(ssid) => forget(&ssid),
+                    None => Err(anyhow::anyhow!("Missing ssid")),
+                };
+                let response = match result {
+                    Ok(_) => json!({"code": 0}),
+                    Err(e) => json!({"code": 1, "message": e.to_string()}),
+                };
+                req.into_ok_response()?
+                    .write_all(response.to_string().as_bytes())?;
+            }
+            Ok(())
+        })?;
+
+        // The provisioning forms, saving the network instead of joining it
+        // right away.
+        let parsers: [(&str, fn(&mut HashMap<String, String>) -> Result<NetConfig>); 3] = [
+            ("/login", |form| {
+                Ok(NetConfig::BuptPortal(forms::bupt_account(form)?))
+            }),
+            ("/wifi", |form| {
+                Ok(NetConfig::NormalWifi(forms::wifi(form)?))
+            }),
+            ("/enterprise", |form| {
+                Ok(NetConfig::Enterprise(forms::enterprise(form)?))
+            }),
+        ];
+        for (path, parse) in parsers {
+            let shared = Arc::clone(&shared);
+            http.fn_handler::<anyhow::Error, _>(path, Method::Post, move |req| {
+                if let Some(mut req) = check_admin(req)? {
+                    let body = read_body_to_string(&mut req)?;
+
+                    if req.header("Content-Type") == Some("application/x-www-form-urlencoded") {
+                        let result = parse_form(&body).and_then(|mut form| {
+                            let priority = forms::priority(&mut form)?;
+                            let config = parse(&mut form)?;
+                            config.validate()?;
+                            request(&shared, config, priority)
+                        });
+                        let response = match result {
+                            Ok(_) => json!({"code": 0}),
+                            Err(e) => json!({"code": 1, "message": e.to_string()}),
+                        };
+                        req.into_ok_response()?
+                            .write_all(response.to_string().as_bytes())?;
+                    } else {
+                        log::info!("Invalid Content-Type");
+                        req.into_response(400, None, &[])?;
+                    }
+                }
+                Ok(())
+            })?;
+        }
+
+        hotspot::register(&mut http, hotspot)?;
+        if hotspot {
+            // Probes only get here while DNS can't be forwarded.
+            captive::register(&mut http, || true)?;
+        }
+        web::serve_frontend(&mut http)?;
+
+        log::info!("Admin page at http://{}.local", ap::hostname()?);
+        log::info!("Admin PIN: {}", auth::pin()?);
+        Ok(Self { shared, http })
+    }
+
+    /// Records the outcome of a supervisor check.
+    pub fn update(&self, esp_wifi: &EspWifi<'static>, config: &NetConfig, result: &Result<()>) {
+        let connected = esp_wifi.is_connected().unwrap_or(false);
+        let ip = match esp_wifi.sta_netif().is_up() {
+            Ok(true) => esp_wifi.sta_netif().get_ip_info().ok().map(|info| info.ip),
+            _ => None,
+        };
+        let rssi = connected
+            .then(|| {
+                let mut info = sys::wifi_ap_record_t::default();
+                esp!(unsafe { sys::esp_wifi_sta_get_ap_info(&mut info) })
+                    .ok()
+                    .map(|_| info.rssi)
+            })
+            .flatten();
+        *self.shared.connection.lock().unwrap() = Connection {
+            ssid: connected.then(|| config.ssid().to_string()),
+            ip,
+            rssi,
+            portal: matches!(config, NetConfig::BuptPortal(_)).then(bupt::is_online),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        };
+    }
+
+    pub fn has_request(&self) -> bool {
+        self.shared.request.lock().unwrap().is_some()
+    }
+
+    /// The network last saved from the page, if the supervisor hasn't
+    /// switched to it yet.
+    pub fn take_request(&self) -> Option<saved::SavedNetwork> {
+        self.shared.request.lock().unwrap().take()
+    }
+}
+
+/// Seconds since boot.
+fn uptime() -> i64 {
+    unsafe { sys::esp_timer_get_time() / 1_000_000 }
+}
+
+/// Saves `config` with `priority`, or the priority it had if not given, and
+/// has the supervisor join it.
+fn request(shared: &Shared, config: NetConfig, priority: Option<u8>) -> Result<()> {
+    let priority = match priority {
+        Some(priority) => priority,
+        None => saved::load_all()?
+            .iter()
+            .find(|saved| saved.config.ssid() == config.ssid())
+            .map_or(0, |saved| saved.priority),
+    };
+    let network = saved::SavedNetwork { config, priority };
+    // New credentials are meant to be used.
+    bupt::set_paused(false);
+    saved::save(network.clone())?;
+    *shared.request.lock().unwrap() = Some(network);
+    shared.waker.wake();
+    Ok(())
+}
+
+/// Removes a saved network, keeping at least one so the device can still
+/// get online.
+fn forget(ssid: &str) -> Result<()> {
+    let networks = saved::load_all()?;
+    if !networks.iter().any(|network| network.config.ssid() == ssid) {
+        anyhow::bail!("未找到已保存的网络: {}", ssid);
+    }
+    if networks.len() <= 1 {
+        anyhow::bail!("至少需要保留一个网络");
+    }
+    saved::remove(ssid)?;
+    Ok(())
+}
@@ -0,0 +1,211 @@
+//! The access point of the device, for provisioning and as a hotspot.
+
+use anyhow::{bail, Result};
+use esp_idf_svc::{
+    ipv4::{self, Mask, RouterConfiguration, Subnet},
+    netif::{EspNetif, NetifConfiguration, NetifStack},
+    wifi::{AccessPointConfiguration, AuthMethod},
+};
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use super::{mac, ScannedNetwork};
+
+/// Followed by the end of the MAC, see [`ssid`].
+const SSID_PREFIX: &str = "BYR-pet";
+/// Channels that don't overlap each other, the AP picks one of them.
+const CHANNELS: [u8; 3] = [1, 6, 11];
+
+/// Channel of the AP while the station isn't connected. Once it is, the AP
+/// has to follow the channel of the station anyway.
+static CHANNEL: AtomicU8 = AtomicU8::new(11);
+
+pub const IP: Ipv4Addr = Ipv4Addr::new(192, 168, 71, 1);
+pub const IP_STRING: &str = "192.168.71.1";
+/// The clients of the AP get their addresses from `IP`/`PREFIX_LEN`.
+const PREFIX_LEN: u8 = 24;
+/// Still resolves to the device once DNS queries are forwarded upstream.
+pub const PORTAL_NAME: &str = "byr-pet.lan";
+/// Key of the AP netif, to look it up without a reference to the driver.
+pub const NETIF_KEY: &str = "WIFI_AP_DEF_BYR_PET";
+
+/// The last two bytes of the factory MAC, e.g. `3F2A`, telling devices apart.
+fn suffix() -> Result<String> {
+    let mac = mac::factory()?;
+    Ok(format!("{:02X}{:02X}", mac[4], mac[5]))
+}
+
+/// `BYR-pet-3F2A`
+pub fn ssid() -> Result<String> {
+    Ok(format!("{}-{}", SSID_PREFIX, suffix()?))
+}
+
+/// `byr-pet-3f2a`, advertised over mDNS.
+pub fn hostname() -> Result<String> {
+    Ok(ssid()?.to_ascii_lowercase())
+}
+
+pub fn channel() -> u8 {
+    CHANNEL.load(Ordering::Relaxed)
+}
+
+/// Uses `channel` from now on, e.g. that of the network the station is about
+/// to join, so the AP doesn't have to move.
+pub fn set_channel(channel: u8) {
+    log::info!("AP channel: {}", channel);
+    CHANNEL.store(channel, Ordering::Relaxed);
+}
+
+/// The least crowded of [`CHANNELS`], counting the networks on overlapping
+/// channels by signal strength.
+pub(super) fn quietest_channel(networks: &[ScannedNetwork]) -> u8 {
+    let load = |channel: u8| -> i32 {
+        networks
+            .iter()
+            .filter(|network| network.channel.abs_diff(channel) < 5)
+            .map(|network| (network.rssi as i32 + 100).max(1))
+            .sum()
+    };
+    CHANNELS
+        .into_iter()
+        .min_by_key(|&channel| load(channel))
+        .unwrap()
+}
+
+/// Characters of the generated passphrase, without look-alikes like 0/o and
+/// 1/l.
+const PASSPHRASE_ALPHABET: &[u8] = b"23456789abcdefghjkmnpqrstuvwxyz";
+const PASSPHRASE_LEN: usize = 10;
+
+/// How the provisioning AP is protected.
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq, Default)]
+pub enum ApSecurity {
+    /// WPA2 with a random passphrase, see [`generated_passphrase`].
+    #[default]
+    Generated,
+    /// No password, anyone nearby can join.
+    Open,
+    /// WPA2 with a passphrase chosen by the user.
+    Custom(String),
+}
+
+impl ApSecurity {
+    /// The mode without the passphrase, as shown to clients.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApSecurity::Generated => "generated",
+            ApSecurity::Open => "open",
+            ApSecurity::Custom(_) => "custom",
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match self {
+            ApSecurity::Custom(passphrase) => check_passphrase(passphrase),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// WPA2 takes 8 to 63 ASCII characters.
+fn check_passphrase(passphrase: &str) -> Result<()> {
+    if !passphrase.is_ascii() {
+        bail!("热点密码只能包含 ASCII 字符");
+    }
+    if !(8..=63).contains(&passphrase.len()) {
+        bail!("热点密码长度应为 8-63 字节");
+    }
+    Ok(())
+}
+
+/// Passphrase for [`ApSecurity::Generated`].
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GeneratedPassphrase(String);
+
+pub fn security() -> Result<ApSecurity> {
+    Ok(crate::nvs::load::<ApSecurity>()?.unwrap_or_default())
+}
+
+pub fn set_security(security: ApSecurity) -> Result<()> {
+    security.validate()?;
+    log::info!("Setting provisioning AP security to {}", security.name());
+    crate::nvs::save(security)
+}
+
+/// The default passphrase, generated on first use and kept. It is printed on
+/// the serial log at boot.
+pub fn generated_passphrase() -> Result<String> {
+    if let Some(GeneratedPassphrase(passphrase)) = crate::nvs::load::<GeneratedPassphrase>()? {
+        return Ok(passphrase);
+    }
+    let mut random = u64::from_ne_bytes(mac::random_bytes());
+    let base = PASSPHRASE_ALPHABET.len() as u64;
+    let passphrase: String = (0..PASSPHRASE_LEN)
+        .map(|_| {
+            let c = PASSPHRASE_ALPHABET[(random % base) as usize] as char;
+            random /= base;
+            c
+        })
+        .collect();
+    log::info!("Generated a passphrase for the provisioning AP");
+    crate::nvs::save(GeneratedPassphrase(passphrase.clone()))?;
+    Ok(passphrase)
+}
+
+/// The passphrase of the provisioning AP, empty if it is open.
+pub fn passphrase() -> Result<String> {
+    Ok(match security()? {
+        ApSecurity::Generated => generated_passphrase()?,
+        ApSecurity::Open => String::new(),
+        ApSecurity::Custom(passphrase) => passphrase,
+    })
+}
+
+/// Whether `ip` is on the network of the AP.
+pub fn is_client(ip: IpAddr) -> bool {
+    let mask = u32::MAX << (32 - PREFIX_LEN);
+    match ip {
+        IpAddr::V4(ip) => u32::from(ip) & mask == u32::from(IP) & mask,
+        IpAddr::V6(_) => false,
+    }
+}
+
+/// A router on `IP`/24 that hands out addresses and itself as DNS server.
+pub fn netif() -> Result<EspNetif> {
+    Ok(EspNetif::new_with_conf(&NetifConfiguration {
+        key: NETIF_KEY.try_into().unwrap(),
+        description: "ap".try_into().unwrap(),
+        route_priority: 10,
+        ip_configuration: ipv4::Configuration::Router(RouterConfiguration {
+            subnet: Subnet {
+                gateway: IP,
+                mask: Mask(PREFIX_LEN),
+            },
+            dhcp_enabled: true,
+            dns: Some(IP),
+            ..Default::default()
+        }),
+        stack: NetifStack::Ap,
+        custom_mac: None,
+    })?)
+}
+
+/// The AP configuration, WPA2 protected unless `password` is empty. A
+/// password WPA2 can't take is an error rather than an open AP.
+pub fn configuration(password: &str) -> Result<AccessPointConfiguration> {
+    let auth_method = if password.is_empty() {
+        AuthMethod::None
+    } else {
+        check_passphrase(password)?;
+        AuthMethod::WPA2Personal
+    };
+    Ok(AccessPointConfiguration {
+        ssid: ssid()?.as_str().try_into().unwrap(),
+        auth_method,
+        password: password.try_into().unwrap(),
+        channel: channel(),
+        ..Default::default()
+    })
+}
@@ -0,0 +1,206 @@
+//! Async counterpart of [`super::connect`], for running the network next to
+//! other tasks on one executor.
+
+use anyhow::Result;
+use embassy_time::{with_timeout, Timer};
+use esp_idf_svc::{
+    eventloop::EspSystemEventLoop,
+    hal::prelude::Peripherals,
+    log::set_target_level,
+    timer::EspTaskTimerService,
+    wifi::{AsyncWifi, AuthMethod, Configuration, EspWifi},
+};
+use std::time::Duration;
+
+use super::{
+    bupt, provisioning, saved,
+    state::{self, Backoff, NetState},
+    Connection, NetConfig, ASSOCIATE_ATTEMPTS, DHCP_TIMEOUT, LOGIN_ATTEMPTS, RETRY_MAX_BACKOFF,
+    RETRY_MIN_BACKOFF,
+};
+
+/// How often the provisioning portal is looked at while waiting for it.
+const PROVISIONING_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+async fn sleep(duration: Duration) {
+    Timer::after_millis(duration.as_millis() as u64).await;
+}
+
+/// Async [`super::ensure_portal_session`].
+async fn ensure_portal_session(account: &bupt::BuptAccount) -> Result<()> {
+    state::transition(NetState::PortalCheck);
+    match bupt::asynch::check().await?.status {
+        bupt::BuptNetStatus::Authenticated => {
+            log::debug!("BUPT-portal session is alive");
+            bupt::set_online(true);
+            Ok(())
+        }
+        bupt::BuptNetStatus::NotAuthenticated(_) if bupt::is_paused() => {
+            log::info!("Logged out from the admin page, not logging in again");
+            bupt::set_online(false);
+            Ok(())
+        }
+        bupt::BuptNetStatus::NotAuthenticated(_) => {
+            state::transition(NetState::PortalLogin);
+            let result = bupt::asynch::login(account).await;
+            bupt::set_online(result.is_ok());
+            result
+        }
+    }
+}
+
+async fn associate(wifi: &mut AsyncWifi<&mut EspWifi<'static>>) -> Result<()> {
+    let mut backoff = Backoff::new(RETRY_MIN_BACKOFF, RETRY_MAX_BACKOFF);
+    for attempt in 1.. {
+        state::transition(NetState::Associating);
+        let result = match wifi.connect().await {
+            Ok(_) => {
+                state::transition(NetState::DhcpWait);
+                let timeout = embassy_time::Duration::from_secs(DHCP_TIMEOUT.as_secs());
+                match with_timeout(timeout, wifi.wait_netif_up()).await {
+                    Ok(result) => result.map_err(anyhow::Error::from),
+                    Err(_) => {
+                        let _ = wifi.disconnect().await;
+                        Err(anyhow::anyhow!("Timed out waiting for an IP address"))
+                    }
+                }
+            }
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok(_) => break,
+            Err(e) if attempt >= ASSOCIATE_ATTEMPTS => {
+                log::error!("Failed to connect wifi: {}, giving up", e);
+                state::transition(NetState::Idle);
+                return Err(e);
+            }
+            Err(e) => {
+                let delay = backoff.next();
+                log::warn!(
+                    "Failed to connect wifi: {}, will retry after {:?}",
+                    e,
+                    delay
+                );
+                state::transition(NetState::Backoff);
+                sleep(delay).await;
+            }
+        }
+    }
+    let ip_info = wifi.wifi().sta_netif().get_ip_info()?;
+    log::info!("Wifi IP info: {:?}", ip_info);
+    Ok(())
+}
+
+/// Async [`super::connect_wifi_with_config`].
+async fn connect_wifi_with_config(
+    config: &NetConfig,
+    detected: Option<AuthMethod>,
+    wifi: &mut AsyncWifi<&mut EspWifi<'static>>,
+) -> Result<()> {
+    if wifi.is_connected()? {
+        wifi.disconnect().await?;
+    }
+    let ssid = super::configure_sta(config, detected, wifi.wifi_mut())?;
+
+    log::info!("Starting wifi...");
+    wifi.start().await?;
+    log::info!("Connecting wifi {}...", ssid);
+    associate(wifi).await?;
+
+    if let NetConfig::BuptPortal(account) = config {
+        let mut backoff = Backoff::new(RETRY_MIN_BACKOFF, RETRY_MAX_BACKOFF);
+        for attempt in 1.. {
+            match ensure_portal_session(account).await {
+                Ok(_) => break,
+                Err(e) if bupt::classify(&e).is_some_and(|e| e.is_permanent()) => {
+                    log::error!("Failed to login to BUPT-portal: {}, giving up", e);
+                    state::transition(NetState::Idle);
+                    return Err(e);
+                }
+                Err(e) if attempt >= LOGIN_ATTEMPTS => {
+                    log::error!("Failed to login to BUPT-portal: {}, giving up", e);
+                    state::transition(NetState::Idle);
+                    return Err(e);
+                }
+                Err(e) => {
+                    let delay = backoff.next();
+                    log::warn!(
+                        "Failed to login to BUPT-portal: {}, will retry after {:?}",
+                        e,
+                        delay
+                    );
+                    state::transition(NetState::Backoff);
+                    sleep(delay).await;
+                }
+            }
+        }
+    }
+    state::transition(NetState::Online);
+    Ok(())
+}
+
+/// Async [`super::connect_best`].
+async fn connect_best(
+    networks: Vec<saved::SavedNetwork>,
+    esp_wifi: &mut EspWifi<'static>,
+    sysloop: EspSystemEventLoop,
+) -> Result<NetConfig> {
+    let mut wifi = AsyncWifi::wrap(esp_wifi, sysloop, EspTaskTimerService::new()?)?;
+
+    state::transition(NetState::Scanning);
+    if !wifi.is_started()? {
+        wifi.set_configuration(&Configuration::Client(Default::default()))?;
+        wifi.start().await?;
+    }
+    let scanned = wifi
+        .scan()
+        .await
+        .map(|aps| super::strongest(&aps))
+        .map_err(anyhow::Error::from);
+
+    for (network, rssi, detected) in super::rank(networks, scanned) {
+        log::info!(
+            "Trying {} (priority {}, {})",
+            network.config.ssid(),
+            network.priority,
+            super::describe_rssi(rssi)
+        );
+        match connect_wifi_with_config(&network.config, detected, &mut wifi).await {
+            Ok(_) => return Ok(network.config),
+            Err(e) => log::warn!("Failed to join {}: {}", network.config.ssid(), e),
+        }
+    }
+    anyhow::bail!("No saved network could be joined")
+}
+
+/// Async [`super::connect`]. The provisioning portal serves its forms on
+/// its own threads, so it is only looked at every now and then.
+pub async fn connect() -> Result<Connection> {
+    set_target_level("wifi", log::LevelFilter::Warn)?;
+    set_target_level("wifi_init", log::LevelFilter::Warn)?;
+
+    #[cfg(feature = "clean_nvs")]
+    saved::clear()?;
+
+    let sysloop = EspSystemEventLoop::take()?;
+    let networks = saved::load_all()?;
+    if networks.is_empty() {
+        let p = provisioning::Provisioner::new(sysloop.clone())?;
+        let config = loop {
+            match p.finished() {
+                Some(config) => break config,
+                None => sleep(PROVISIONING_POLL_INTERVAL).await,
+            }
+        };
+        return super::provisioned(p, sysloop, config);
+    }
+
+    log::info!("Loaded {} saved networks: {:?}", networks.len(), &networks);
+    let mut wifi = super::create_wifi(Peripherals::take()?.modem, sysloop.clone())?;
+    let config = connect_best(networks, &mut wifi, sysloop.clone()).await?;
+    Ok(Connection {
+        wifi,
+        sysloop,
+        config,
+    })
+}
@@ -0,0 +1,333 @@
+//! The admin PIN and the sessions it opens. State-changing endpoints require
+//! a session cookie, so that joining the AP alone doesn't allow changing the
+//! device.
+
+use anyhow::{bail, Result};
+use embedded_svc::http::Headers;
+use esp_idf_svc::{
+    http::{
+        server::{EspHttpConnection, EspHttpServer, Request},
+        Method,
+    },
+    io::Write,
+    sys,
+};
+use serde_json::json;
+use std::{
+    mem::ManuallyDrop,
+    net::{IpAddr, TcpStream},
+    os::fd::FromRawFd,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{
+    ap, mac,
+    web::{check_host_and_log, parse_form, read_body_to_string, take_field},
+};
+
+const COOKIE_NAME: &str = "byr_pet_session";
+const SESSION_LIFETIME: Duration = Duration::from_secs(30 * 60);
+const MAX_SESSIONS: usize = 4;
+/// Wrong PINs in a row from a client before its logins are refused for
+/// `LOCKOUT`.
+const MAX_FAILURES: u32 = 5;
+const LOCKOUT: Duration = Duration::from_secs(60);
+/// Clients whose failures are remembered, the oldest is forgotten first.
+const MAX_CLIENTS: usize = 8;
+/// Time for the response to a reboot request to go out.
+const REBOOT_DELAY: Duration = Duration::from_millis(500);
+pub const UNAUTHORIZED: &str = "请先输入管理员 PIN";
+
+/// The PIN, generated on first use or chosen by the user. Like the rest of
+/// NVS it is stored in plain text: NVS encryption only protects it together
+/// with flash encryption.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct AdminPin(String);
+
+/// Wrong PINs from one client.
+struct Failures {
+    ip: IpAddr,
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+struct Sessions {
+    tokens: Vec<(String, Instant)>,
+    failures: Vec<Failures>,
+}
+
+static SESSIONS: Mutex<Sessions> = Mutex::new(Sessions {
+    tokens: Vec::new(),
+    failures: Vec::new(),
+});
+
+/// The current PIN. Six random digits are generated and kept on first use,
+/// they are printed on the serial log at boot.
+pub fn pin() -> Result<String> {
+    if let Some(AdminPin(pin)) = crate::nvs::load::<AdminPin>()? {
+        return Ok(pin);
+    }
+    let pin = format!("{:06}", u32::from_ne_bytes(mac::random_bytes()) % 1_000_000);
+    log::info!("Generated an admin PIN");
+    crate::nvs::save(AdminPin(pin.clone()))?;
+    Ok(pin)
+}
+
+pub fn set_pin(pin: &str) -> Result<()> {
+    if !(4..=12).contains(&pin.len()) || !pin.chars().all(|c| c.is_ascii_digit()) {
+        bail!("PIN 应为 4-12 位数字");
+    }
+    log::info!("Setting admin PIN");
+    crate::nvs::save(AdminPin(pin.to_string()))?;
+    // Sessions opened with the old PIN end.
+    SESSIONS.lock().unwrap().tokens.clear();
+    Ok(())
+}
+
+/// Compares in constant time, so the response time doesn't leak the PIN.
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Opens a session if `candidate` is the PIN, returns its token. Wrong PINs
+/// lock out only `client`, so one client can't lock out everyone.
+pub fn login(client: IpAddr, candidate: &str) -> Result<String> {
+    let now = Instant::now();
+    let mut sessions = SESSIONS.lock().unwrap();
+    let failures = &mut sessions.failures;
+    let index = match failures.iter().position(|entry| entry.ip == client) {
+        Some(index) => index,
+        None => {
+            // Clients whose lockout is over are forgotten before locked ones.
+            failures.retain(|entry| {
+                entry.count > 0 || entry.locked_until.is_some_and(|until| until > now)
+            });
+            if failures.len() >= MAX_CLIENTS {
+                failures.remove(0);
+            }
+            failures.push(Failures {
+                ip: client,
+                count: 0,
+                locked_until: None,
+            });
+            failures.len() - 1
+        }
+    };
+    let client_failures = &mut failures[index];
+    if client_failures
+        .locked_until
+        .is_some_and(|until| until > now)
+    {
+        bail!("尝试次数过多，请稍后再试");
+    }
+    if !same(candidate, &pin()?) {
+        client_failures.count += 1;
+        if client_failures.count >= MAX_FAILURES {
+            log::warn!(
+                "Too many wrong admin PINs from {}, locking for {:?}",
+                client,
+                LOCKOUT
+            );
+            client_failures.count = 0;
+            client_failures.locked_until = Some(now + LOCKOUT);
+        }
+        bail!("PIN 错误");
+    }
+    failures.remove(index);
+
+    let mut bytes = [0u8; 16];
+    unsafe { sys::esp_fill_random(bytes.as_mut_ptr() as *mut _, bytes.len()) };
+    let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    sessions.tokens.retain(|(_, expires)| *expires > now);
+    if sessions.tokens.len() >= MAX_SESSIONS {
+        sessions.tokens.remove(0);
+    }
+    sessions
+        .tokens
+        .push((token.clone(), now + SESSION_LIFETIME));
+    Ok(token)
+}
+
+/// Whether `req` carries the cookie of an open session.
+pub fn is_authenticated(req: &Request<&mut EspHttpConnection>) -> bool {
+    let Some(token) = req.header("Cookie").and_then(|cookies| {
+        cookies.split(';').find_map(|cookie| {
+            let (name, value) = cookie.trim().split_once('=')?;
+            (name == COOKIE_NAME).then_some(value)
+        })
+    }) else {
+        return false;
+    };
+    let now = Instant::now();
+    SESSIONS
+        .lock()
+        .unwrap()
+        .tokens
+        .iter()
+        .any(|(open, expires)| *expires > now && same(open, token))
+}
+
+/// The address of the client that sent `req`.
+fn peer_ip(req: &mut Request<&mut EspHttpConnection>) -> Result<IpAddr> {
+    let handle = req.connection().raw_connection()?.handle();
+    let fd = unsafe { sys::httpd_req_to_sockfd(handle) };
+    if fd < 0 {
+        bail!("Request without socket");
+    }
+    // The socket belongs to the server, which closes it.
+    let stream = ManuallyDrop::new(unsafe { TcpStream::from_raw_fd(fd) });
+    Ok(stream.peer_addr()?.ip())
+}
+
+/// [`check_host_and_log`] for state-changing endpoints: answers 401 without
+/// a session.
+pub fn check_admin<'a, 'b>(
+    req: Request<&'a mut EspHttpConnection<'b>>,
+) -> anyhow::Result<Option<Request<&'a mut EspHttpConnection<'b>>>> {
+    let Some(req) = check_host_and_log(req)? else {
+        return Ok(None);
+    };
+    if is_authenticated(&req) {
+        return Ok(Some(req));
+    }
+    req.into_response(401, None, &[("Content-Type", "application/json")])?
+        .write_all(
+            json!({"code": 401, "message": UNAUTHORIZED})
+                .to_string()
+                .as_bytes(),
+        )?;
+    Ok(None)
+}
+
+/// Registers GET `/api/session`, POST `/session` to log in with the PIN, and
+/// the admin-only POST `/pin` and POST `/ap` to change the PIN and the
+/// protection of the provisioning AP, and POST `/reboot` for the settings
+/// that only apply at boot. `mode` tells the frontend which server it talks
+/// to, `provisioning` or `admin`.
+pub fn register(http: &mut EspHttpServer<'static>, mode: &'static str) -> anyhow::Result<()> {
+    http.fn_handler::<anyhow::Error, _>("/api/session", Method::Get, move |req| {
+        if let Some(req) = check_host_and_log(req)? {
+            let status = json!({
+                "mode": mode,
+                "authenticated": is_authenticated(&req),
+                "ap_security": ap::security()?.name(),
+            });
+            req.into_response(200, None, &[("Content-Type", "application/json")])?
+                .write_all(status.to_string().as_bytes())?;
+        }
+        Ok(())
+    })?;
+
+    http.fn_handler::<anyhow::Error, _>("/session", Method::Post, |req| {
+        if let Some(mut req) = check_host_and_log(req)? {
+            let body = read_body_to_string(&mut req)?;
+
+            if req.header("Content-Type") == Some("application/x-www-form-urlencoded") {
+                let mut form = parse_form(&body)?;
+                let result = peer_ip(&mut req).and_then(|client| {
+                    form.remove("pin")
+                        .ok_or(anyhow::anyhow!("Missing pin"))
+                        .and_then(|pin| login(client, pin.trim()))
+                });
+                match result {
+                    Ok(token) => {
+                        let cookie = format!(
+                            "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict",
+                            COOKIE_NAME,
+                            token,
+                            SESSION_LIFETIME.as_secs()
+                        );
+                        req.into_response(200, None, &[("Set-Cookie", cookie.as_str())])?
+                            .write_all(json!({"code": 0}).to_string().as_bytes())?;
+                    }
+                    Err(e) => {
+                        req.into_ok_response()?.write_all(
+                            json!({"code": 1, "message": e.to_string()})
+                                .to_string()
+                                .as_bytes(),
+                        )?;
+                    }
+                }
+            } else {
+                log::info!("Invalid Content-Type");
+                req.into_response(400, None, &[])?;
+            }
+        }
+        Ok(())
+    })?;
+
+    http.fn_handler::<anyhow::Error, _>("/pin", Method::Post, |req| {
+        if let Some(mut req) = check_admin(req)? {
+            let body = read_body_to_string(&mut req)?;
+
+            if req.header("Content-Type") == Some("application/x-www-form-urlencoded") {
+                let mut form = parse_form(&body)?;
+                let result = form
+                    .remove("pin")
+                    .ok_or(anyhow::anyhow!("Missing pin"))
+                    .and_then(|pin| set_pin(pin.trim()));
+                let response = match result {
+                    Ok(_) => json!({"code": 0}),
+                    Err(e) => json!({"code": 1, "message": e.to_string()}),
+                };
+                req.into_ok_response()?
+                    .write_all(response.to_string().as_bytes())?;
+            } else {
+                log::info!("Invalid Content-Type");
+                req.into_response(400, None, &[])?;
+            }
+        }
+        Ok(())
+    })?;
+
+    http.fn_handler::<anyhow::Error, _>("/ap", Method::Post, |req| {
+        if let Some(mut req) = check_admin(req)? {
+            let body = read_body_to_string(&mut req)?;
+
+            if req.header("Content-Type") == Some("application/x-www-form-urlencoded") {
+                let mut form = parse_form(&body)?;
+                let result = match form.remove("security").as_deref() {
+                    Some("generated") => Ok(ap::ApSecurity::Generated),
+                    Some("open") => Ok(ap::ApSecurity::Open),
+                    Some("custom") => take_field(&mut form, "passphrase")
+                        .map(ap::ApSecurity::Custom)
+                        .ok_or(anyhow::anyhow!("Missing passphrase")),
+                    _ => Err(anyhow::anyhow!("Missing security")),
+                }
+                .and_then(ap::set_security);
+                let response = match result {
+                    Ok(_) => json!({"code": 0}),
+                    Err(e) => json!({"code": 1, "message": e.to_string()}),
+                };
+                req.into_ok_response()?
+                    .write_all(response.to_string().as_bytes())?;
+            } else {
+                log::info!("Invalid Content-Type");
+                req.into_response(400, None, &[])?;
+            }
+        }
+        Ok(())
+    })?;
+
+    http.fn_handler::<anyhow::Error, _>("/reboot", Method::Post, |req| {
+        if let Some(req) = check_admin(req)? {
+            req.into_ok_response()?
+                .write_all(json!({"code": 0}).to_string().as_bytes())?;
+            log::info!("Rebooting as requested");
+            thread::spawn(|| {
+                thread::sleep(REBOOT_DELAY);
+                unsafe { sys::esp_restart() };
+            });
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
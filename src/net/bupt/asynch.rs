@@ -0,0 +1,91 @@
+//! Async BUPT-portal on the device, over `async-io` sockets. The executor has
+//! to run on `async_io::block_on`, which needs the eventfd VFS registered.
+
+use anyhow::Result;
+use async_io::Async;
+use embassy_time::with_timeout;
+use futures_lite::{AsyncReadExt, AsyncWriteExt};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use bupt_portal::{
+    encode_request, parse_response, split_url, AsyncHttpClient, CheckResult, Method, Response,
+    Strategy, MAX_RESPONSE_LEN,
+};
+
+use super::{probe_config, BuptAccount};
+
+/// Same as the blocking [`super::EspHttpClient`].
+const TIMEOUT: embassy_time::Duration = embassy_time::Duration::from_secs(20);
+
+/// HTTP/1.0 over a plain socket, one connection per request.
+pub struct EspAsyncHttpClient;
+
+impl EspAsyncHttpClient {
+    async fn exchange(
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Response> {
+        let (host, path) = split_url(url)?;
+        // lwIP only resolves blocking. The portal and the probes are either
+        // IP addresses or answered by the campus DNS right away.
+        let with_port = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:80", host)
+        };
+        let addr = with_port
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Failed to resolve {}", host))?;
+        let mut stream = Async::<TcpStream>::connect(addr).await?;
+        stream
+            .write_all(&encode_request(method, host, path, headers, body))
+            .await?;
+        let mut data = Vec::new();
+        stream
+            .take(MAX_RESPONSE_LEN as u64)
+            .read_to_end(&mut data)
+            .await?;
+        parse_response(&data)
+    }
+}
+
+impl AsyncHttpClient for EspAsyncHttpClient {
+    async fn request(
+        &mut self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Response> {
+        with_timeout(TIMEOUT, Self::exchange(method, url, headers, body))
+            .await
+            .map_err(|_| anyhow::anyhow!("Timed out requesting {}", url))?
+    }
+}
+
+/// Async [`super::check`].
+pub async fn check() -> Result<CheckResult> {
+    let config = probe_config()?;
+    match config.strategy {
+        Strategy::Sequential => {
+            bupt_portal::check_sequential_async(&mut EspAsyncHttpClient, &config.probes).await
+        }
+        Strategy::Race => {
+            bupt_portal::check_race_async(|| Ok(EspAsyncHttpClient), &config.probes).await
+        }
+    }
+}
+
+/// Async [`super::login`].
+pub async fn login(account: &BuptAccount) -> Result<()> {
+    bupt_portal::login_with_async(
+        &mut EspAsyncHttpClient,
+        &probe_config()?.probes,
+        &account.username,
+        &account.password,
+    )
+    .await
+}
@@ -0,0 +1,73 @@
+use anyhow::Result;
+use embedded_svc::http::client::Client;
+use esp_idf_svc::http::client::{Configuration, EspHttpConnection, FollowRedirectsPolicy};
+use std::time::Duration;
+
+use bupt_portal::{HttpClient, Method, Response, MAX_BODY_LEN};
+
+/// Headers the portal flow looks at. `EspHttpConnection` can only be queried
+/// by name, so these are copied into the [`Response`].
+const HEADERS: &[&str] = &["Location", "Set-Cookie", "Content-Type"];
+
+pub struct EspHttpClient {
+    client: Client<EspHttpConnection>,
+}
+
+impl EspHttpClient {
+    pub fn new() -> Result<Self> {
+        let connection = EspHttpConnection::new(&Configuration {
+            follow_redirects_policy: FollowRedirectsPolicy::FollowNone,
+            timeout: Some(Duration::from_secs(20)),
+            ..Default::default()
+        })?;
+        Ok(Self {
+            client: Client::wrap(connection),
+        })
+    }
+}
+
+impl HttpClient for EspHttpClient {
+    fn request(
+        &mut self,
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Response> {
+        let method = match method {
+            Method::Get => embedded_svc::http::Method::Get,
+            Method::Head => embedded_svc::http::Method::Head,
+            Method::Post => embedded_svc::http::Method::Post,
+        };
+        let mut request = self.client.request(method, url, headers)?;
+        if !body.is_empty() {
+            request.write(body)?;
+        }
+        let mut response = request.submit()?;
+
+        let status = response.status();
+        let headers = HEADERS
+            .iter()
+            .filter_map(|name| {
+                response
+                    .header(name)
+                    .map(|value| (name.to_string(), value.to_string()))
+            })
+            .collect();
+
+        let mut data: Vec<u8> = Vec::new();
+        let mut buffer = [0u8; 1024];
+        while data.len() < MAX_BODY_LEN {
+            match response.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(size) => data.extend_from_slice(&buffer[..size]),
+            }
+        }
+
+        Ok(Response {
+            status,
+            headers,
+            body: data,
+        })
+    }
+}
@@ -0,0 +1,108 @@
+//! BUPT-portal on the device: the saved account, probes configuration from
+//! NVS and [`EspHttpClient`]. The protocol itself lives in the `bupt-portal`
+//! crate.
+
+#[cfg(feature = "embassy")]
+pub mod asynch;
+mod esp;
+
+use anyhow::Result;
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+pub use bupt_portal::{classify, BuptNetStatus, ProbeConfig, SessionStatus, Strategy};
+pub use esp::EspHttpClient;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct BuptAccount {
+    pub username: String,
+    pub password: String,
+    /// MAC of an already registered device whose portal session is taken
+    /// over.
+    #[serde(default, with = "crate::net::mac::optional")]
+    pub mac: Option<[u8; 6]>,
+}
+
+impl fmt::Debug for BuptAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let password_length = self.password.len();
+        let hidden_password = "*".repeat(password_length);
+        f.debug_struct("BuptAccount")
+            .field("username", &self.username)
+            .field("password", &hidden_password)
+            .field("mac", &self.mac)
+            .finish()
+    }
+}
+
+/// Set by a logout from the admin page, so the supervisor doesn't log right
+/// back in.
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Whether automatic logins are held back after a manual logout.
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+pub fn set_paused(paused: bool) {
+    PAUSED.store(paused, Ordering::Relaxed);
+}
+
+/// What the supervisor found out about the session on its last check, so
+/// the admin page doesn't have to ask the portal itself.
+static ONLINE: AtomicBool = AtomicBool::new(false);
+
+pub fn is_online() -> bool {
+    ONLINE.load(Ordering::Relaxed)
+}
+
+pub fn set_online(online: bool) {
+    ONLINE.store(online, Ordering::Relaxed);
+}
+
+/// Probes configuration saved in NVS, or the built-in defaults.
+pub fn probe_config() -> Result<ProbeConfig> {
+    Ok(crate::nvs::load::<ProbeConfig>()?.unwrap_or_default())
+}
+
+/// Saves probes set on the admin page, `None` restores the defaults.
+pub fn set_probe_config(config: Option<ProbeConfig>) -> Result<()> {
+    match config {
+        Some(config) => {
+            config.validate()?;
+            log::info!("Setting connectivity probes: {:?}", config);
+            crate::nvs::save(config)
+        }
+        None => crate::nvs::remove::<ProbeConfig>().map(|_| ()),
+    }
+}
+
+/// Checks connectivity with every configured probe.
+pub fn check() -> Result<bupt_portal::CheckResult> {
+    let config = probe_config()?;
+    match config.strategy {
+        Strategy::Sequential => {
+            bupt_portal::check_sequential(&mut EspHttpClient::new()?, &config.probes)
+        }
+        Strategy::Race => bupt_portal::check_race(EspHttpClient::new, &config.probes),
+    }
+}
+
+pub fn login(account: &BuptAccount) -> Result<()> {
+    bupt_portal::login_with(
+        &mut EspHttpClient::new()?,
+        &probe_config()?.probes,
+        &account.username,
+        &account.password,
+    )
+}
+
+pub fn logout() -> Result<()> {
+    bupt_portal::logout_with(&mut EspHttpClient::new()?, &probe_config()?.probes)
+}
+
+pub fn status() -> Result<SessionStatus> {
+    bupt_portal::status_with(&mut EspHttpClient::new()?, &probe_config()?.probes)
+}
@@ -0,0 +1,117 @@
+//! Answers the connectivity checks of the operating systems. They get
+//! redirected to the portal, which pops up the sign-in page, until the device
+//! is online, then get the expected answer so they stop asking.
+
+use std::sync::Arc;
+
+use embedded_svc::http::Headers;
+use esp_idf_svc::{
+    http::{server::EspHttpServer, Method},
+    io::Write,
+};
+
+use super::ap::IP_STRING;
+
+const APPLE_SUCCESS: &str = "<HTML><HEAD><TITLE>Success</TITLE></HEAD><BODY>Success</BODY></HTML>";
+
+struct Probe {
+    path: &'static str,
+    /// The answer that means "online".
+    status: u16,
+    content_type: &'static str,
+    body: &'static str,
+}
+
+const PROBES: &[Probe] = &[
+    // Android and ChromeOS
+    Probe {
+        path: "/generate_204",
+        status: 204,
+        content_type: "text/plain",
+        body: "",
+    },
+    Probe {
+        path: "/gen_204",
+        status: 204,
+        content_type: "text/plain",
+        body: "",
+    },
+    // iOS and macOS
+    Probe {
+        path: "/hotspot-detect.html",
+        status: 200,
+        content_type: "text/html",
+        body: APPLE_SUCCESS,
+    },
+    Probe {
+        path: "/library/test/success.html",
+        status: 200,
+        content_type: "text/html",
+        body: APPLE_SUCCESS,
+    },
+    // Windows 10 and later
+    Probe {
+        path: "/connecttest.txt",
+        status: 200,
+        content_type: "text/plain",
+        body: "Microsoft Connect Test",
+    },
+    // Older Windows
+    Probe {
+        path: "/ncsi.txt",
+        status: 200,
+        content_type: "text/plain",
+        body: "Microsoft NCSI",
+    },
+    // Firefox
+    Probe {
+        path: "/success.txt",
+        status: 200,
+        content_type: "text/plain",
+        body: "success\n",
+    },
+];
+
+/// Registers a handler for every probe, whatever the host. `online` tells
+/// whether to report the device as online. Has to be registered before the
+/// frontend.
+pub fn register(
+    http: &mut EspHttpServer<'static>,
+    online: impl Fn() -> bool + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+    let online = Arc::new(online);
+    for probe in PROBES {
+        let online = Arc::clone(&online);
+        http.fn_handler::<anyhow::Error, _>(probe.path, Method::Get, move |req| {
+            let online = online();
+            log::info!(
+                "Captive portal probe {}{}, online: {}",
+                req.host().unwrap_or("Unknown"),
+                req.uri(),
+                online
+            );
+            if online {
+                req.into_response(
+                    probe.status,
+                    None,
+                    &[
+                        ("Content-Type", probe.content_type),
+                        ("Cache-Control", "no-cache"),
+                    ],
+                )?
+                .write_all(probe.body.as_bytes())?;
+            } else {
+                req.into_response(
+                    302,
+                    None,
+                    &[
+                        ("Location", format!("http://{}/", IP_STRING).as_str()),
+                        ("Cache-Control", "no-cache"),
+                    ],
+                )?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
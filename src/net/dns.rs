@@ -0,0 +1,131 @@
+use std::{
+    net::{Ipv4Addr, UdpSocket},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use captive_dns::{captive_reply, Forwarder, DNS_MAX_LEN};
+
+use super::ap;
+
+pub struct DnsServer {
+    ip: Ipv4Addr,
+    /// `None` while every name resolves to `ip`.
+    forwarder: Arc<Mutex<Option<Forwarder>>>,
+    handle: Option<thread::JoinHandle<()>>,
+    stop_tx: Option<mpsc::Sender<()>>,
+}
+
+impl DnsServer {
+    pub fn new(ip: Ipv4Addr) -> Self {
+        Self {
+            ip,
+            forwarder: Arc::new(Mutex::new(None)),
+            handle: None,
+            stop_tx: None,
+        }
+    }
+
+    pub fn start(&mut self) -> anyhow::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        self.stop_tx = Some(tx);
+
+        let ip = self.ip;
+        let forwarder = Arc::clone(&self.forwarder);
+        log::info!("Dns Server started");
+
+        let handle = thread::spawn(move || {
+            let udp_server = UdpSocket::bind("0.0.0.0:53").expect("Could not bind to address");
+            udp_server
+                .set_read_timeout(Some(Duration::from_secs(1)))
+                .expect("Could not set read timeout");
+            let mut buf = [0u8; DNS_MAX_LEN];
+
+            loop {
+                if rx.try_recv().is_ok() {
+                    break;
+                }
+
+                match udp_server.recv_from(&mut buf) {
+                    Ok((len, addr)) => {
+                        log::debug!("Received UDP packet from {}", addr);
+                        let mut forwarder = forwarder.lock().unwrap();
+                        // Bound to every interface for the upstream answers,
+                        // but only the clients of the AP are served.
+                        if !ap::is_client(addr.ip())
+                            && !forwarder.as_ref().is_some_and(|f| f.is_upstream(addr))
+                        {
+                            log::debug!("Ignoring DNS packet from {}", addr);
+                            continue;
+                        }
+                        if let Some(forwarder) = forwarder.as_mut() {
+                            forwarder.handle(&udp_server, &buf[..len], addr);
+                        } else if let Some(response) = captive_reply(&buf[..len], ip) {
+                            if let Err(e) = udp_server.send_to(&response, addr) {
+                                log::warn!("Failed to send DNS response: {}", e);
+                            }
+                        }
+                    }
+                    Err(ref e)
+                        if e.kind() == std::io::ErrorKind::WouldBlock
+                            || e.kind() == std::io::ErrorKind::TimedOut =>
+                    {
+                        if let Some(forwarder) = forwarder.lock().unwrap().as_mut() {
+                            forwarder.expire(Instant::now());
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("Error: {}", e);
+                    }
+                }
+            }
+        });
+
+        self.handle = Some(handle);
+
+        Ok(())
+    }
+
+    /// Forwards queries to the `upstream` resolvers, with a cache. Only the
+    /// `local` names still resolve to the server address. Nothing changes if
+    /// already forwarding to `upstream`.
+    pub fn forward(&self, upstream: Vec<Ipv4Addr>, local: &[&str]) {
+        let mut forwarder = self.forwarder.lock().unwrap();
+        if forwarder
+            .as_ref()
+            .is_some_and(|forwarder| forwarder.upstream() == upstream)
+        {
+            return;
+        }
+        log::info!("DNS server forwarding to {:?}", upstream);
+        let local = local.iter().map(|name| name.to_string()).collect();
+        *forwarder = Some(Forwarder::new(self.ip, upstream, local));
+    }
+
+    /// Resolves every name to the server address again.
+    pub fn captive(&self) {
+        log::info!("DNS server answering every name");
+        *self.forwarder.lock().unwrap() = None;
+    }
+
+    pub fn stop(&mut self) {
+        if self.stop_tx.is_none() {
+            return;
+        }
+        if let Some(tx) = self.stop_tx.take() {
+            let _ = tx.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        log::info!("Dns Server stopped");
+        self.handle = None;
+    }
+}
+
+impl Drop for DnsServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::{bail, Result};
+use esp_idf_svc::sys::{self, esp};
+use std::{fmt, sync::Mutex};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EapMethod {
+    /// PEAP with MSCHAPv2 as the inner method, what eduroam usually runs.
+    Peap,
+    /// EAP-TTLS with MSCHAPv2 as the inner method.
+    Ttls,
+}
+
+/// An 802.1X network such as eduroam.
+///
+/// WPA3-Enterprise networks running in transition mode are joined as
+/// WPA2-Enterprise.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct EnterpriseWifi {
+    pub ssid: String,
+    pub identity: String,
+    /// Outer identity sent in the clear, `identity` is used when empty.
+    #[serde(default)]
+    pub anonymous_identity: String,
+    pub password: String,
+    pub eap_method: EapMethod,
+    /// PEM encoded CA certificate of the RADIUS server. The server is not
+    /// verified without one.
+    pub ca_cert: Option<String>,
+    pub ip: Option<super::ip::IpSettings>,
+    #[serde(default, with = "super::mac::optional")]
+    pub mac: Option<[u8; 6]>,
+}
+
+impl EnterpriseWifi {
+    pub fn validate(&self) -> Result<()> {
+        if self.ssid.is_empty() || self.ssid.len() > 32 {
+            bail!("SSID 长度应为 1-32 字节");
+        }
+        if self.identity.is_empty() {
+            bail!("身份不能为空");
+        }
+        if let Some(ca_cert) = &self.ca_cert {
+            if !ca_cert.contains("-----BEGIN CERTIFICATE-----") {
+                bail!("CA 证书应为 PEM 格式");
+            }
+        }
+        if let Some(mac) = &self.mac {
+            super::mac::validate_clone(mac)?;
+        }
+        match &self.ip {
+            Some(ip) => ip.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for EnterpriseWifi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let password_length = self.password.len();
+        let hidden_password = "*".repeat(password_length);
+        f.debug_struct("EnterpriseWifi")
+            .field("ssid", &self.ssid)
+            .field("identity", &self.identity)
+            .field("anonymous_identity", &self.anonymous_identity)
+            .field("password", &hidden_password)
+            .field("eap_method", &self.eap_method)
+            .field("ca_cert", &self.ca_cert.is_some())
+            .field("ip", &self.ip)
+            .field("mac", &self.mac)
+            .finish()
+    }
+}
+
+// The supplicant keeps a pointer to the certificate instead of copying it.
+static CA_CERT: Mutex<Option<Box<[u8]>>> = Mutex::new(None);
+
+/// Sets up the EAP client for `wifi`. Must be called before connecting.
+pub fn enable(wifi: &EnterpriseWifi) -> Result<()> {
+    let outer = if wifi.anonymous_identity.is_empty() {
+        &wifi.identity
+    } else {
+        &wifi.anonymous_identity
+    };
+
+    unsafe {
+        esp!(sys::esp_eap_client_set_identity(
+            outer.as_ptr(),
+            outer.len() as _
+        ))?;
+        esp!(sys::esp_eap_client_set_username(
+            wifi.identity.as_ptr(),
+            wifi.identity.len() as _
+        ))?;
+        esp!(sys::esp_eap_client_set_password(
+            wifi.password.as_ptr(),
+            wifi.password.len() as _
+        ))?;
+        if wifi.eap_method == EapMethod::Ttls {
+            esp!(sys::esp_eap_client_set_ttls_phase2_method(
+                sys::esp_eap_ttls_phase2_types_ESP_EAP_TTLS_PHASE2_MSCHAPV2
+            ))?;
+        }
+
+        let mut ca_cert = CA_CERT.lock().unwrap();
+        match &wifi.ca_cert {
+            Some(pem) => {
+                // mbedTLS wants PEM data NUL terminated, with the NUL counted
+                let mut data = pem.as_bytes().to_vec();
+                data.push(0);
+                let data = data.into_boxed_slice();
+                esp!(sys::esp_eap_client_set_ca_cert(
+                    data.as_ptr(),
+                    data.len() as _
+                ))?;
+                *ca_cert = Some(data);
+            }
+            None => {
+                log::warn!("No CA certificate, the RADIUS server will not be verified");
+                sys::esp_eap_client_clear_ca_cert();
+                *ca_cert = None;
+            }
+        }
+
+        esp!(sys::esp_wifi_sta_enterprise_enable())?;
+    }
+    Ok(())
+}
+
+/// Turns the EAP client off again before joining a non-802.1X network.
+pub fn disable() -> Result<()> {
+    unsafe {
+        esp!(sys::esp_wifi_sta_enterprise_disable())?;
+        sys::esp_eap_client_clear_identity();
+        sys::esp_eap_client_clear_username();
+        sys::esp_eap_client_clear_password();
+        sys::esp_eap_client_clear_ca_cert();
+    }
+    *CA_CERT.lock().unwrap() = None;
+    Ok(())
+}
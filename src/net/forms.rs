@@ -0,0 +1,120 @@
+//! Reads the network forms the frontend posts, shared by the provisioning
+//! portal and the admin page.
+
+use anyhow::Result;
+use std::collections::HashMap;
+
+use super::{
+    bupt::BuptAccount,
+    enterprise::{EapMethod, EnterpriseWifi},
+    ip::{self, IpSettings, StaticIp},
+    mac,
+    web::take_field,
+    Wifi, WifiAuth,
+};
+
+/// Reads the optional `ip_address`, `ip_mask`, `ip_gateway`, `ip_dns` and
+/// `hostname` fields.
+fn parse_ip_settings(form: &mut HashMap<String, String>) -> Result<Option<IpSettings>> {
+    let static_ip = match take_field(form, "ip_address") {
+        Some(address) => {
+            let dns = take_field(form, "ip_dns")
+                .map(|dns| dns.trim().parse())
+                .transpose()?;
+            Some(StaticIp {
+                address: address.trim().parse()?,
+                mask: ip::parse_mask(
+                    take_field(form, "ip_mask")
+                        .as_deref()
+                        .unwrap_or("24")
+                        .trim(),
+                )?,
+                gateway: take_field(form, "ip_gateway")
+                    .ok_or(anyhow::anyhow!("Missing ip_gateway"))?
+                    .trim()
+                    .parse()?,
+                dns,
+                secondary_dns: None,
+            })
+        }
+        None => None,
+    };
+    let hostname = take_field(form, "hostname").map(|hostname| hostname.trim().to_string());
+    if static_ip.is_none() && hostname.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(IpSettings {
+        static_ip,
+        hostname,
+    }))
+}
+
+/// Reads the optional `mac` field, a MAC to clone.
+fn parse_mac(form: &mut HashMap<String, String>) -> Result<Option<[u8; 6]>> {
+    take_field(form, "mac")
+        .map(|mac| mac::parse(&mac))
+        .transpose()
+}
+
+/// Reads the optional `priority` field of a saved network.
+pub fn priority(form: &mut HashMap<String, String>) -> Result<Option<u8>> {
+    take_field(form, "priority")
+        .map(|priority| {
+            priority
+                .trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("优先级应为 0-255 的整数"))
+        })
+        .transpose()
+}
+
+/// The form posted to `/login`.
+pub fn bupt_account(form: &mut HashMap<String, String>) -> Result<BuptAccount> {
+    Ok(BuptAccount {
+        username: form
+            .remove("username")
+            .ok_or(anyhow::anyhow!("Missing username"))?,
+        password: form
+            .remove("password")
+            .ok_or(anyhow::anyhow!("Missing password"))?,
+        mac: parse_mac(form)?,
+    })
+}
+
+/// The form posted to `/wifi`.
+pub fn wifi(form: &mut HashMap<String, String>) -> Result<Wifi> {
+    let auth_method = match form.remove("auth_method").as_deref() {
+        None | Some("") | Some("auto") => None,
+        Some(name) => Some(WifiAuth::from_name(name)?),
+    };
+    Ok(Wifi {
+        ssid: form.remove("ssid").ok_or(anyhow::anyhow!("Missing ssid"))?,
+        password: form.remove("password").unwrap_or_default(),
+        auth_method,
+        ip: parse_ip_settings(form)?,
+        mac: parse_mac(form)?,
+    })
+}
+
+/// The form posted to `/enterprise`.
+pub fn enterprise(form: &mut HashMap<String, String>) -> Result<EnterpriseWifi> {
+    let eap_method = match form.remove("eap_method").as_deref() {
+        None | Some("peap") => EapMethod::Peap,
+        Some("ttls") => EapMethod::Ttls,
+        Some(method) => anyhow::bail!("Unsupported EAP method: {}", method),
+    };
+    Ok(EnterpriseWifi {
+        ssid: form.remove("ssid").ok_or(anyhow::anyhow!("Missing ssid"))?,
+        identity: form
+            .remove("identity")
+            .ok_or(anyhow::anyhow!("Missing identity"))?,
+        anonymous_identity: form.remove("anonymous_identity").unwrap_or_default(),
+        password: form
+            .remove("password")
+            .ok_or(anyhow::anyhow!("Missing password"))?,
+        eap_method,
+        ca_cert: take_field(form, "ca_cert"),
+        ip: parse_ip_settings(form)?,
+        mac: parse_mac(form)?,
+    })
+}
@@ -0,0 +1,241 @@
+//! Repeater mode: keeps the AP up after provisioning and shares the STA
+//! connection with its clients through NAPT, so one portal login serves a
+//! few more devices.
+
+use anyhow::{bail, Result};
+use embedded_svc::http::Headers;
+use esp_idf_svc::{
+    http::{server::EspHttpServer, Method},
+    io::Write,
+    sys::{self, esp},
+    wifi::{AccessPointConfiguration, Configuration, EspWifi},
+};
+use serde_json::json;
+use std::{ffi::CString, fmt, net::Ipv4Addr};
+
+use super::{
+    ap,
+    auth::check_admin,
+    dns, mac,
+    web::{parse_form, read_body_to_string, take_field},
+};
+
+/// Repeater settings, kept in NVS.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+pub struct HotspotConfig {
+    pub enabled: bool,
+    /// WPA2 passphrase of the AP while it shares the connection.
+    pub password: String,
+}
+
+impl HotspotConfig {
+    fn validate(&self) -> Result<()> {
+        if !self.password.is_ascii() {
+            bail!("热点密码只能包含 ASCII 字符");
+        }
+        if self.enabled && !(8..=63).contains(&self.password.len()) {
+            bail!("热点密码长度应为 8-63 字节");
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for HotspotConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let password_length = self.password.len();
+        let hidden_password = "*".repeat(password_length);
+        f.debug_struct("HotspotConfig")
+            .field("enabled", &self.enabled)
+            .field("password", &hidden_password)
+            .finish()
+    }
+}
+
+pub fn config() -> Result<HotspotConfig> {
+    Ok(crate::nvs::load::<HotspotConfig>()?.unwrap_or_default())
+}
+
+pub fn set_config(config: HotspotConfig) -> Result<()> {
+    config.validate()?;
+    log::info!("Setting hotspot to {:?}", config);
+    crate::nvs::save(config)
+}
+
+/// The AP configuration of the hotspot, `None` when it is disabled.
+pub fn ap_configuration() -> Result<Option<AccessPointConfiguration>> {
+    let config = config()?;
+    config
+        .enabled
+        .then(|| ap::configuration(&config.password))
+        .transpose()
+}
+
+/// A station connected to the AP.
+#[derive(serde::Serialize, Debug)]
+pub struct Client {
+    pub mac: String,
+    /// Leased by the DHCP server, `None` until the client asked for one.
+    pub ip: Option<Ipv4Addr>,
+    pub rssi: i8,
+}
+
+pub fn clients() -> Result<Vec<Client>> {
+    let mut list = sys::wifi_sta_list_t::default();
+    esp!(unsafe { sys::esp_wifi_ap_get_sta_list(&mut list) })?;
+    let stations = &list.sta[..list.num as usize];
+
+    let mut leases: Vec<_> = stations
+        .iter()
+        .map(|station| sys::esp_netif_pair_mac_ip_t {
+            mac: station.mac,
+            ..Default::default()
+        })
+        .collect();
+    let key = CString::new(ap::NETIF_KEY)?;
+    let netif = unsafe { sys::esp_netif_get_handle_from_ifkey(key.as_ptr()) };
+    if !netif.is_null() && !leases.is_empty() {
+        esp!(unsafe {
+            sys::esp_netif_dhcps_get_clients_by_mac(netif, leases.len() as _, leases.as_mut_ptr())
+        })?;
+    }
+
+    Ok(stations
+        .iter()
+        .zip(&leases)
+        .map(|(station, lease)| Client {
+            mac: mac::format(&station.mac),
+            ip: (lease.ip.addr != 0).then(|| Ipv4Addr::from(u32::from_be(lease.ip.addr))),
+            rssi: station.rssi,
+        })
+        .collect())
+}
+
+/// Lets `dns` forward to the resolvers the station got. Keeps answering every
+/// name locally if there are none.
+pub fn forward_dns(esp_wifi: &EspWifi<'static>, dns: &dns::DnsServer) {
+    let ip_info = match esp_wifi.sta_netif().get_ip_info() {
+        Ok(ip_info) => ip_info,
+        Err(e) => {
+            log::warn!("Failed to get IP info: {}", e);
+            return;
+        }
+    };
+    let upstream: Vec<_> = [ip_info.dns, ip_info.secondary_dns]
+        .into_iter()
+        .flatten()
+        .filter(|ip| !ip.is_unspecified())
+        .collect();
+    if upstream.is_empty() {
+        log::warn!("No upstream DNS server, keep answering every name locally");
+        return;
+    }
+    dns.forward(upstream, &[ap::PORTAL_NAME]);
+}
+
+/// Registers the admin-only GET `/api/hotspot` and POST `/hotspot`. `active`
+/// tells the frontend whether it is served by a running hotspot, which lists
+/// its clients.
+pub fn register(http: &mut EspHttpServer<'static>, active: bool) -> Result<()> {
+    http.fn_handler::<anyhow::Error, _>("/api/hotspot", Method::Get, move |req| {
+        if let Some(req) = check_admin(req)? {
+            let config = config()?;
+            let clients = if active {
+                clients().unwrap_or_else(|e| {
+                    log::warn!("Failed to list hotspot clients: {}", e);
+                    Vec::new()
+                })
+            } else {
+                Vec::new()
+            };
+            let status = json!({
+                "enabled": config.enabled,
+                "password_set": !config.password.is_empty(),
+                "active": active,
+                "clients": clients,
+            });
+            req.into_response(200, None, &[("Content-Type", "application/json")])?
+                .write_all(status.to_string().as_bytes())?;
+        }
+        Ok(())
+    })?;
+
+    http.fn_handler::<anyhow::Error, _>("/hotspot", Method::Post, |req| {
+        if let Some(mut req) = check_admin(req)? {
+            let body = read_body_to_string(&mut req)?;
+
+            if req.header("Content-Type") == Some("application/x-www-form-urlencoded") {
+                let mut form = parse_form(&body)?;
+                let result = config().and_then(|current| {
+                    set_config(HotspotConfig {
+                        enabled: form.remove("enabled").as_deref() == Some("true"),
+                        // An empty password keeps the current one.
+                        password: take_field(&mut form, "password").unwrap_or(current.password),
+                    })
+                });
+                let response = match result {
+                    Ok(_) => json!({"code": 0}),
+                    Err(e) => json!({"code": 1, "message": e.to_string()}),
+                };
+                req.into_ok_response()?
+                    .write_all(response.to_string().as_bytes())?;
+            } else {
+                log::info!("Invalid Content-Type");
+                req.into_response(400, None, &[])?;
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// The running hotspot: NAPT on the AP and a forwarding DNS server. The
+/// admin page lists its clients.
+pub struct Hotspot {
+    dns: dns::DnsServer,
+}
+
+impl Hotspot {
+    /// Starts sharing the connection. The driver has to run in mixed mode
+    /// already, as set up by [`super::configure_sta`] while the hotspot is
+    /// enabled.
+    pub fn start(esp_wifi: &EspWifi<'static>) -> Result<Self> {
+        if !matches!(esp_wifi.get_configuration()?, Configuration::Mixed(..)) {
+            bail!("The AP is not up");
+        }
+        esp!(unsafe { sys::esp_netif_napt_enable(esp_wifi.ap_netif().handle()) })?;
+
+        let mut dns = dns::DnsServer::new(ap::IP);
+        dns.start()?;
+        forward_dns(esp_wifi, &dns);
+
+        log::info!("Sharing the connection on `{}`", ap::ssid()?);
+        Ok(Self { dns })
+    }
+
+    /// Follows the resolvers of the station, which change with the network.
+    pub fn refresh(&self, esp_wifi: &EspWifi<'static>) {
+        forward_dns(esp_wifi, &self.dns);
+    }
+
+    /// Answers every name locally while the station is offline, so clients
+    /// end up on the admin page instead of waiting for upstream timeouts.
+    pub fn captive(&self) {
+        self.dns.captive();
+    }
+}
+
+impl Drop for Hotspot {
+    /// Stops routing for the clients, the AP itself may stay up.
+    fn drop(&mut self) {
+        let key = CString::new(ap::NETIF_KEY).unwrap();
+        let netif = unsafe { sys::esp_netif_get_handle_from_ifkey(key.as_ptr()) };
+        if netif.is_null() {
+            return;
+        }
+        match esp!(unsafe { sys::esp_netif_napt_disable(netif) }) {
+            Ok(_) => log::info!("Stopped sharing the connection"),
+            Err(e) => log::warn!("Failed to disable NAPT: {}", e),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use anyhow::{bail, Result};
+use esp_idf_svc::{
+    sys::{self, esp},
+    wifi::EspWifi,
+};
+use std::{
+    ffi::{CStr, CString},
+    net::Ipv4Addr,
+    sync::{Mutex, OnceLock},
+};
+
+/// Longest hostname lwIP's DHCP client accepts.
+const MAX_HOSTNAME_LEN: usize = 30;
+
+/// A fixed address instead of a DHCP lease.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StaticIp {
+    pub address: Ipv4Addr,
+    /// Prefix length, e.g. 24 for 255.255.255.0.
+    pub mask: u8,
+    pub gateway: Ipv4Addr,
+    pub dns: Option<Ipv4Addr>,
+    pub secondary_dns: Option<Ipv4Addr>,
+}
+
+/// IPv4 settings of the station interface.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct IpSettings {
+    /// DHCP is used when not set.
+    pub static_ip: Option<StaticIp>,
+    pub hostname: Option<String>,
+}
+
+impl IpSettings {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(hostname) = &self.hostname {
+            if hostname.is_empty()
+                || hostname.len() > MAX_HOSTNAME_LEN
+                || hostname.starts_with('-')
+                || !hostname
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                bail!("主机名只能包含字母、数字和 -, 长度不超过 30");
+            }
+        }
+        if let Some(ip) = &self.static_ip {
+            if ip.mask == 0 || ip.mask > 30 {
+                bail!("子网掩码无效");
+            }
+            let mask = u32::MAX << (32 - ip.mask);
+            if u32::from(ip.address) & mask != u32::from(ip.gateway) & mask {
+                bail!("网关 {} 不在 {}/{} 网段内", ip.gateway, ip.address, ip.mask);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a prefix length (`24`) or a dotted netmask (`255.255.255.0`).
+pub fn parse_mask(mask: &str) -> Result<u8> {
+    if let Ok(prefix) = mask.parse::<u8>() {
+        return Ok(prefix);
+    }
+    let bits = u32::from(mask.parse::<Ipv4Addr>()?);
+    if bits.leading_ones() != bits.count_ones() {
+        bail!("子网掩码无效: {}", mask);
+    }
+    Ok(bits.leading_ones() as u8)
+}
+
+/// Settings currently applied to the STA netif, which starts out with DHCP.
+static CURRENT: Mutex<IpSettings> = Mutex::new(IpSettings {
+    static_ip: None,
+    hostname: None,
+});
+
+/// Hostname the STA netif came with, restored when a network doesn't set
+/// one.
+static DEFAULT_HOSTNAME: OnceLock<CString> = OnceLock::new();
+
+/// Applies `settings`, or DHCP when there are none, to the STA netif in
+/// place. The station has to be disconnected, the driver and with it the
+/// hotspot keep running.
+pub fn apply(esp_wifi: &mut EspWifi<'static>, settings: Option<&IpSettings>) -> Result<()> {
+    let default = IpSettings::default();
+    let settings = settings.unwrap_or(&default);
+    let mut current = CURRENT.lock().unwrap();
+    if *current == *settings {
+        return Ok(());
+    }
+    log::info!("Configuring STA netif: {:?}", settings);
+    let netif = esp_wifi.sta_netif().handle();
+
+    let default_hostname = match DEFAULT_HOSTNAME.get() {
+        Some(hostname) => hostname,
+        None => {
+            let mut hostname: *const std::ffi::c_char = std::ptr::null();
+            esp!(unsafe { sys::esp_netif_get_hostname(netif, &mut hostname) })?;
+            DEFAULT_HOSTNAME.get_or_init(|| match hostname.is_null() {
+                // What lwIP uses when the netif has none.
+                true => CString::new("espressif").unwrap(),
+                false => unsafe { CStr::from_ptr(hostname) }.to_owned(),
+            })
+        }
+    };
+    let hostname = match &settings.hostname {
+        Some(hostname) => CString::new(hostname.as_str())?,
+        None => default_hostname.clone(),
+    };
+    esp!(unsafe { sys::esp_netif_set_hostname(netif, hostname.as_ptr()) })?;
+
+    match &settings.static_ip {
+        Some(ip) => {
+            ignore(
+                unsafe { sys::esp_netif_dhcpc_stop(netif) },
+                sys::ESP_ERR_ESP_NETIF_DHCP_ALREADY_STOPPED,
+            )?;
+            let info = sys::esp_netif_ip_info_t {
+                ip: ip4(ip.address),
+                netmask: ip4(Ipv4Addr::from(u32::MAX << (32 - ip.mask))),
+                gw: ip4(ip.gateway),
+            };
+            esp!(unsafe { sys::esp_netif_set_ip_info(netif, &info) })?;
+            let servers = [
+                (sys::esp_netif_dns_type_t_ESP_NETIF_DNS_MAIN, ip.dns),
+                (
+                    sys::esp_netif_dns_type_t_ESP_NETIF_DNS_BACKUP,
+                    ip.secondary_dns,
+                ),
+            ];
+            for (kind, server) in servers {
+                let mut dns = sys::esp_netif_dns_info_t::default();
+                dns.ip.type_ = sys::ESP_IPADDR_TYPE_V4 as _;
+                dns.ip.u_addr.ip4 = ip4(server.unwrap_or(Ipv4Addr::UNSPECIFIED));
+                esp!(unsafe { sys::esp_netif_set_dns_info(netif, kind, &mut dns) })?;
+            }
+        }
+        None => ignore(
+            unsafe { sys::esp_netif_dhcpc_start(netif) },
+            sys::ESP_ERR_ESP_NETIF_DHCP_ALREADY_STARTED,
+        )?,
+    }
+    *current = settings.clone();
+    Ok(())
+}
+
+fn ip4(addr: Ipv4Addr) -> sys::esp_ip4_addr_t {
+    // lwIP keeps addresses in network byte order.
+    sys::esp_ip4_addr_t {
+        addr: u32::from_ne_bytes(addr.octets()),
+    }
+}
+
+/// Treats `allowed` as success, for starting or stopping the DHCP client
+/// when it already is.
+fn ignore(code: sys::esp_err_t, allowed: u32) -> Result<()> {
+    if code == allowed as sys::esp_err_t {
+        return Ok(());
+    }
+    Ok(esp!(code)?)
+}
@@ -0,0 +1,212 @@
+use anyhow::{bail, Result};
+use esp_idf_svc::{
+    sys::{self, esp},
+    wifi::{EspWifi, WifiDeviceId},
+};
+use serde::{de::IntoDeserializer, Deserialize};
+use std::{hash::Hasher, sync::OnceLock};
+use twox_hash::XxHash64;
+
+/// Which MAC address the station uses.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MacPolicy {
+    /// The address burnt into the chip.
+    #[default]
+    Factory,
+    /// Generated on first use and kept, so the portal always sees the same
+    /// device.
+    RandomOnce,
+    /// Derived from the SSID and a per-device secret: stable for a network,
+    /// different between networks.
+    PerNetwork,
+    /// A new address on every boot. Every boot counts as a new device on the
+    /// portal.
+    EveryBoot,
+}
+
+impl MacPolicy {
+    /// Parses the snake_case name, e.g. `per_network`.
+    pub fn from_name(name: &str) -> Result<Self> {
+        Self::deserialize(name.into_deserializer())
+            .map_err(|_: serde::de::value::Error| anyhow::anyhow!("不支持的 MAC 策略: {}", name))
+    }
+}
+
+/// Parses `AA:BB:CC:DD:EE:FF`, `-` is accepted as separator too.
+pub fn parse(mac: &str) -> Result<[u8; 6]> {
+    let octets = mac
+        .trim()
+        .split([':', '-'])
+        .map(|octet| u8::from_str_radix(octet, 16))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| anyhow::anyhow!("MAC 地址格式错误: {}", mac))?;
+    octets
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("MAC 地址格式错误: {}", mac))
+}
+
+/// Formats `mac` as `AA:BB:CC:DD:EE:FF`.
+pub fn format(mac: &[u8; 6]) -> String {
+    mac.iter()
+        .map(|octet| format!("{:02X}", octet))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// (De)serializes an optional MAC as `AA:BB:CC:DD:EE:FF` in human-readable
+/// formats such as JSON, and as bytes otherwise, so NVS blobs keep their
+/// layout. Use with `#[serde(default, with = "...")]`.
+pub mod optional {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        mac: &Option<[u8; 6]>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            mac.map(|mac| super::format(&mac)).serialize(serializer)
+        } else {
+            mac.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<[u8; 6]>, D::Error> {
+        if deserializer.is_human_readable() {
+            Option::<String>::deserialize(deserializer)?
+                .map(|mac| super::parse(&mac).map_err(D::Error::custom))
+                .transpose()
+        } else {
+            Option::<[u8; 6]>::deserialize(deserializer)
+        }
+    }
+}
+
+/// A cloned MAC has to be unicast and locally administered.
+pub fn validate_clone(mac: &[u8; 6]) -> Result<()> {
+    if mac[0] & 0x01 != 0 {
+        bail!("MAC 地址不能是组播地址");
+    }
+    if mac[0] & 0x02 == 0 {
+        bail!("MAC 地址必须是本地管理地址 (第一个字节的第二位为 1)");
+    }
+    Ok(())
+}
+
+/// Random MAC kept for [`MacPolicy::RandomOnce`].
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PersistedMac([u8; 6]);
+
+/// Key for [`MacPolicy::PerNetwork`], never leaves the device.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DeviceSecret([u8; 16]);
+
+pub fn policy() -> Result<MacPolicy> {
+    Ok(crate::nvs::load::<MacPolicy>()?.unwrap_or_default())
+}
+
+pub fn set_policy(policy: MacPolicy) -> Result<()> {
+    log::info!("Setting MAC policy to {:?}", policy);
+    crate::nvs::save(policy)
+}
+
+pub(super) fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    unsafe { sys::esp_fill_random(bytes.as_mut_ptr() as *mut _, N) };
+    bytes
+}
+
+/// Makes `mac` a unicast, locally administered address.
+fn local_unicast(mut mac: [u8; 6]) -> [u8; 6] {
+    mac[0] = (mac[0] & 0xFE) | 0x02;
+    mac
+}
+
+fn persisted_mac() -> Result<[u8; 6]> {
+    if let Some(PersistedMac(mac)) = crate::nvs::load::<PersistedMac>()? {
+        return Ok(mac);
+    }
+    let mac = local_unicast(random_bytes());
+    crate::nvs::save(PersistedMac(mac))?;
+    Ok(mac)
+}
+
+fn device_secret() -> Result<[u8; 16]> {
+    if let Some(DeviceSecret(secret)) = crate::nvs::load::<DeviceSecret>()? {
+        return Ok(secret);
+    }
+    let secret = random_bytes();
+    crate::nvs::save(DeviceSecret(secret))?;
+    Ok(secret)
+}
+
+fn network_mac(ssid: &str) -> Result<[u8; 6]> {
+    let mut hasher = XxHash64::default();
+    hasher.write(&device_secret()?);
+    hasher.write(ssid.as_bytes());
+    let hash = hasher.finish().to_be_bytes();
+    let mut mac = [0u8; 6];
+    mac.copy_from_slice(&hash[..6]);
+    Ok(local_unicast(mac))
+}
+
+/// The MAC every boot picks once, for [`MacPolicy::EveryBoot`].
+static BOOT_MAC: OnceLock<[u8; 6]> = OnceLock::new();
+
+/// Sets the station MAC before joining `ssid`: the `cloned` one if the
+/// network has one, otherwise according to the stored policy. The station
+/// has to be disconnected. With the hotspot up only the station interface
+/// is taken down, otherwise the driver is stopped and callers start it
+/// again.
+pub fn apply(esp_wifi: &mut EspWifi<'static>, ssid: &str, cloned: Option<[u8; 6]>) -> Result<()> {
+    let policy = policy()?;
+    let mac = match (cloned, policy) {
+        (Some(mac), _) => mac,
+        (None, policy) => policy_mac(policy, ssid)?,
+    };
+
+    if esp_wifi.get_mac(WifiDeviceId::Sta)? == mac {
+        return Ok(());
+    }
+    if !esp_wifi.is_started()? {
+        esp_wifi.set_mac(WifiDeviceId::Sta, mac)?;
+    } else if mode()? == sys::wifi_mode_t_WIFI_MODE_APSTA {
+        // The address can only be set while the interface is disabled.
+        esp!(unsafe { sys::esp_wifi_set_mode(sys::wifi_mode_t_WIFI_MODE_AP) })?;
+        let result = esp_wifi.set_mac(WifiDeviceId::Sta, mac);
+        esp!(unsafe { sys::esp_wifi_set_mode(sys::wifi_mode_t_WIFI_MODE_APSTA) })?;
+        result?;
+    } else {
+        esp_wifi.stop()?;
+        esp_wifi.set_mac(WifiDeviceId::Sta, mac)?;
+    }
+    match cloned {
+        Some(_) => log::info!("Set MAC address to {:02X?} (cloned)", mac),
+        None => log::info!("Set MAC address to {:02X?} ({:?})", mac, policy),
+    }
+    Ok(())
+}
+
+fn mode() -> Result<sys::wifi_mode_t> {
+    let mut mode = sys::wifi_mode_t_WIFI_MODE_NULL;
+    esp!(unsafe { sys::esp_wifi_get_mode(&mut mode) })?;
+    Ok(mode)
+}
+
+/// The station MAC burnt into the chip.
+pub fn factory() -> Result<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    esp!(unsafe { sys::esp_read_mac(mac.as_mut_ptr(), sys::esp_mac_type_t_ESP_MAC_WIFI_STA) })?;
+    Ok(mac)
+}
+
+fn policy_mac(policy: MacPolicy, ssid: &str) -> Result<[u8; 6]> {
+    Ok(match policy {
+        MacPolicy::Factory => factory()?,
+        MacPolicy::RandomOnce => persisted_mac()?,
+        MacPolicy::PerNetwork => network_mac(ssid)?,
+        MacPolicy::EveryBoot => *BOOT_MAC.get_or_init(|| local_unicast(random_bytes())),
+    })
+}
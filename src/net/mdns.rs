@@ -0,0 +1,18 @@
+//! Advertises the device as `byr-pet-xxxx.local` on both interfaces, so its
+//! web page can be found without knowing the address.
+
+use anyhow::Result;
+use esp_idf_svc::mdns::EspMdns;
+
+use super::ap;
+
+/// Answers until the returned handle is dropped.
+pub fn start() -> Result<EspMdns> {
+    let hostname = ap::hostname()?;
+    let mut mdns = EspMdns::take()?;
+    mdns.set_hostname(&hostname)?;
+    mdns.set_instance_name(&ap::ssid()?)?;
+    mdns.add_service(None, "_http", "_tcp", 80, &[("path", "/")])?;
+    log::info!("Advertising http://{}.local", hostname);
+    Ok(mdns)
+}
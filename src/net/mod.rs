@@ -1,111 +1,375 @@
+mod admin;
+mod ap;
+#[cfg(feature = "embassy")]
+pub mod asynch;
+mod auth;
 mod bupt;
+mod captive;
+mod dns;
+mod enterprise;
+mod forms;
+mod hotspot;
+mod ip;
+mod mac;
+mod mdns;
 mod provisioning;
+mod saved;
+mod state;
+mod supervisor;
+mod web;
 
 use anyhow::{bail, Result};
-use esp_idf_hal::delay;
 use esp_idf_svc::{
     eventloop::EspSystemEventLoop,
     hal::{peripheral, prelude::Peripherals},
     log::set_target_level,
-    wifi::{AuthMethod, BlockingWifi, ClientConfiguration, Configuration, EspWifi},
+    wifi::{
+        AccessPointInfo, AuthMethod, BlockingWifi, ClientConfiguration, Configuration, EspWifi,
+        PmfConfiguration,
+    },
+};
+use serde::{de::IntoDeserializer, Deserialize};
+use state::{NetEvent, NetState};
+use std::{
+    fmt, thread,
+    time::{Duration, Instant},
 };
-use std::fmt;
 
-fn connect_wifi_with_config(
-    config: NetConfig,
+fn create_wifi(
     modem: impl peripheral::Peripheral<P = esp_idf_svc::hal::modem::Modem> + 'static,
     sysloop: EspSystemEventLoop,
 ) -> Result<Box<EspWifi<'static>>> {
     let nvs = crate::nvs::nvs();
-    let mut bupt_account = None;
-    let (auth_method, ssid, pass) = match config {
-        NetConfig::BuptPortal(account) => {
-            bupt_account = Some(account);
-            (AuthMethod::None, "BUPT-portal".to_string(), String::new())
-        }
-        NetConfig::NormalWifi(wifi) => (AuthMethod::WPA2Personal, wifi.ssid, wifi.password),
-    };
-    let mut esp_wifi = EspWifi::new(modem, sysloop.clone(), Some(nvs))?;
+    let mut esp_wifi = EspWifi::new(modem, sysloop, Some(nvs))?;
+    esp_wifi.swap_netif_ap(ap::netif()?)?;
+    Ok(Box::new(esp_wifi))
+}
+
+/// Association attempts before giving up on a network.
+const ASSOCIATE_ATTEMPTS: u32 = 6;
+const ASSOCIATE_TIMEOUT: Duration = Duration::from_secs(20);
+const DHCP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Login attempts before giving up on the BUPT-portal.
+const LOGIN_ATTEMPTS: u32 = 6;
+const RETRY_MIN_BACKOFF: Duration = Duration::from_secs(2);
+const RETRY_MAX_BACKOFF: Duration = Duration::from_secs(60);
 
-    #[cfg(feature = "random_mac")]
+/// How long the driver gets to report a requested disconnect.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Disconnects the station and waits for the driver to report it, so the
+/// event can't be taken for a rejection of the next association.
+fn disconnect(esp_wifi: &mut EspWifi<'static>, events: &state::Events) -> Result<()> {
+    esp_wifi.disconnect()?;
+    if events
+        .wait(DISCONNECT_TIMEOUT, &[NetEvent::Disconnected])
+        .is_none()
     {
-        use esp_idf_svc::wifi::WifiDeviceId;
-        let mac = generate_random_mac();
-        log::info!("Generated random MAC: {:02X?}", mac);
-        esp_wifi.set_mac(WifiDeviceId::Sta, mac)?;
-        log::info!(
-            "Set MAC address to {:02X?}",
-            esp_wifi.get_mac(WifiDeviceId::Sta)?
-        );
+        log::debug!("No disconnect event within {:?}", DISCONNECT_TIMEOUT);
     }
+    Ok(())
+}
 
-    let mut wifi = BlockingWifi::wrap(&mut esp_wifi, sysloop)?;
+/// Where [`associate`] is, each with the deadline of its step.
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Associating(Instant),
+    DhcpWait(Instant),
+    Backoff(Instant),
+}
 
-    wifi.set_configuration(&Configuration::Client(ClientConfiguration {
-        ssid: heapless::String::<32>::from_iter(ssid.chars()),
-        password: heapless::String::<64>::from_iter(pass.chars()),
-        channel: None,
-        auth_method,
-        ..Default::default()
-    }))?;
+/// Associates with the configured AP and waits for an IP address, driven by
+/// the station events. A failed attempt is retried after a backoff, up to
+/// `attempts` in total. The driver must be started and disconnected, with
+/// `events` subscribed before the disconnect.
+fn associate(esp_wifi: &mut EspWifi<'static>, events: &state::Events, attempts: u32) -> Result<()> {
+    let mut backoff = state::Backoff::new(RETRY_MIN_BACKOFF, RETRY_MAX_BACKOFF);
+    let mut attempt = 1;
+    state::transition(NetState::Associating);
+    esp_wifi.connect()?;
+    let mut step = Step::Associating(Instant::now() + ASSOCIATE_TIMEOUT);
+    loop {
+        let deadline = match step {
+            Step::Associating(deadline) | Step::DhcpWait(deadline) | Step::Backoff(deadline) => {
+                deadline
+            }
+        };
+        let event = events.next(deadline);
+        let failure = match (step, event) {
+            (Step::Associating(_), Some(NetEvent::Connected)) => {
+                // A static address is there as soon as the link is.
+                if esp_wifi.sta_netif().is_up()? {
+                    break;
+                }
+                state::transition(NetState::DhcpWait);
+                step = Step::DhcpWait(Instant::now() + DHCP_TIMEOUT);
+                continue;
+            }
+            (Step::DhcpWait(_), Some(NetEvent::GotIp)) => break,
+            (Step::Associating(_), Some(NetEvent::Disconnected)) => "Association rejected",
+            (Step::DhcpWait(_), Some(NetEvent::Disconnected)) => {
+                "Disconnected while waiting for an IP address"
+            }
+            (Step::Associating(_), None) => {
+                disconnect(esp_wifi, events)?;
+                "Association timed out"
+            }
+            (Step::DhcpWait(_), None) => {
+                disconnect(esp_wifi, events)?;
+                "Timed out waiting for an IP address"
+            }
+            (Step::Backoff(_), None) => {
+                attempt += 1;
+                state::transition(NetState::Associating);
+                esp_wifi.connect()?;
+                step = Step::Associating(Instant::now() + ASSOCIATE_TIMEOUT);
+                continue;
+            }
+            (_, Some(event)) => {
+                log::debug!("Ignoring {:?} in {:?}", event, step);
+                continue;
+            }
+        };
 
-    log::info!("Starting wifi...");
-    wifi.start()?;
-    log::info!("Connecting wifi {}...", ssid);
-    let delay: delay::Delay = Default::default();
+        if attempt >= attempts {
+            log::error!("Failed to connect wifi: {}, giving up", failure);
+            state::transition(NetState::Idle);
+            bail!(failure);
+        }
+        let delay = backoff.next();
+        log::warn!(
+            "Failed to connect wifi: {}, will retry after {:?}",
+            failure,
+            delay
+        );
+        state::transition(NetState::Backoff);
+        step = Step::Backoff(Instant::now() + delay);
+    }
 
-    for retry in 0..10 {
-        match wifi.connect() {
-            Ok(_) => break,
-            Err(e) => {
-                log::warn!(
-                    "Failed to connect wifi: {}, will retry after 10 seconds...",
-                    e
-                );
-            }
+    let ip_info = esp_wifi.sta_netif().get_ip_info()?;
+    log::info!("Wifi IP info: {:?}", ip_info);
+    Ok(())
+}
+
+/// Checks the BUPT-portal session and logs in when it is gone.
+fn ensure_portal_session(account: &bupt::BuptAccount) -> Result<()> {
+    state::transition(NetState::PortalCheck);
+    match bupt::check()?.status {
+        bupt::BuptNetStatus::Authenticated => {
+            log::debug!("BUPT-portal session is alive");
+            bupt::set_online(true);
+            Ok(())
         }
-        delay.delay_ms(1000 * 10);
-        if retry == 9 {
-            log::error!("Retry limit exceeded");
-            bail!("Failed to connect to wifi");
-        } else {
-            log::info!("Retrying...");
+        bupt::BuptNetStatus::NotAuthenticated(_) if bupt::is_paused() => {
+            log::info!("Logged out from the admin page, not logging in again");
+            bupt::set_online(false);
+            Ok(())
+        }
+        bupt::BuptNetStatus::NotAuthenticated(_) => {
+            state::transition(NetState::PortalLogin);
+            let result = bupt::login(account);
+            bupt::set_online(result.is_ok());
+            result
         }
     }
+}
 
-    log::info!("Waiting for DHCP lease...");
-    wifi.wait_netif_up()?;
+/// Applies everything about `config` that has to be set before starting the
+/// driver: EAP, IP settings, the STA configuration and the MAC. Returns the
+/// SSID.
+fn configure_sta(
+    config: &NetConfig,
+    detected: Option<AuthMethod>,
+    esp_wifi: &mut EspWifi<'static>,
+) -> Result<heapless::String<32>> {
+    let client_config = config.client_configuration(detected);
+    let ssid = client_config.ssid.clone();
+    config.setup_eap()?;
+    ip::apply(esp_wifi, config.ip_settings())?;
+    // Keep the hotspot up while switching networks.
+    let configuration = match hotspot::ap_configuration()? {
+        Some(ap_config) => Configuration::Mixed(client_config, ap_config),
+        None => Configuration::Client(client_config),
+    };
+    esp_wifi.set_configuration(&configuration)?;
+    mac::apply(esp_wifi, &ssid, config.cloned_mac())?;
+    Ok(ssid)
+}
 
-    let ip_info = wifi.wifi().sta_netif().get_ip_info()?;
-    log::info!("Wifi DHCP info: {:?}", ip_info);
+/// Joins `config`. `detected` is the auth method seen in a scan, used when
+/// the network doesn't store one.
+fn connect_wifi_with_config(
+    config: &NetConfig,
+    detected: Option<AuthMethod>,
+    esp_wifi: &mut EspWifi<'static>,
+    sysloop: EspSystemEventLoop,
+) -> Result<()> {
+    let events = state::Events::subscribe(&sysloop)?;
+    let mut wifi = BlockingWifi::wrap(&mut *esp_wifi, sysloop)?;
 
-    if let Some(account) = bupt_account {
-        for retry in 0..10 {
-            match bupt::login(&account) {
+    if wifi.is_connected()? {
+        disconnect(wifi.wifi_mut(), &events)?;
+    }
+    let ssid = configure_sta(config, detected, wifi.wifi_mut())?;
+
+    log::info!("Starting wifi...");
+    wifi.start()?;
+    log::info!("Connecting wifi {}...", ssid);
+    associate(esp_wifi, &events, ASSOCIATE_ATTEMPTS)?;
+
+    if let NetConfig::BuptPortal(account) = config {
+        let mut backoff = state::Backoff::new(RETRY_MIN_BACKOFF, RETRY_MAX_BACKOFF);
+        for attempt in 1.. {
+            match ensure_portal_session(account) {
                 Ok(_) => break,
+                Err(e) if bupt::classify(&e).is_some_and(|e| e.is_permanent()) => {
+                    log::error!("Failed to login to BUPT-portal: {}, giving up", e);
+                    state::transition(NetState::Idle);
+                    return Err(e);
+                }
+                Err(e) if attempt >= LOGIN_ATTEMPTS => {
+                    log::error!("Failed to login to BUPT-portal: {}, giving up", e);
+                    state::transition(NetState::Idle);
+                    return Err(e);
+                }
                 Err(e) => {
+                    let delay = backoff.next();
                     log::warn!(
-                        "Failed to login to BUPT-portal: {}, will retry after 10 seconds...",
-                        e
+                        "Failed to login to BUPT-portal: {}, will retry after {:?}",
+                        e,
+                        delay
                     );
+                    state::transition(NetState::Backoff);
+                    thread::sleep(delay);
                 }
             }
-            delay.delay_ms(1000 * 10);
-            if retry == 9 {
-                log::error!("Retry limit exceeded");
-                bail!("Failed to login to BUPT-portal");
-            } else {
-                log::info!("Retrying...");
-            }
         }
     }
-    Ok(Box::new(esp_wifi))
+    state::transition(NetState::Online);
+    Ok(())
+}
+
+/// Serializable mirror of [`AuthMethod`], named the way the frontend shows it.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum WifiAuth {
+    #[serde(rename = "open")]
+    None,
+    #[serde(rename = "wep")]
+    Wep,
+    #[serde(rename = "wpa")]
+    Wpa,
+    #[serde(rename = "wpa2")]
+    Wpa2Personal,
+    #[serde(rename = "wpa/wpa2")]
+    WpaWpa2Personal,
+    #[serde(rename = "wpa2-enterprise")]
+    Wpa2Enterprise,
+    #[serde(rename = "wpa3")]
+    Wpa3Personal,
+    #[serde(rename = "wpa2/wpa3")]
+    Wpa2Wpa3Personal,
+    #[serde(rename = "wapi")]
+    WapiPersonal,
+}
+
+impl WifiAuth {
+    /// Parses the name used by the frontend, e.g. `wpa2/wpa3`.
+    fn from_name(name: &str) -> Result<Self> {
+        Self::deserialize(name.into_deserializer())
+            .map_err(|_: serde::de::value::Error| anyhow::anyhow!("不支持的认证方式: {}", name))
+    }
+}
+
+impl From<AuthMethod> for WifiAuth {
+    fn from(auth_method: AuthMethod) -> Self {
+        match auth_method {
+            AuthMethod::None => WifiAuth::None,
+            AuthMethod::WEP => WifiAuth::Wep,
+            AuthMethod::WPA => WifiAuth::Wpa,
+            AuthMethod::WPA2Personal => WifiAuth::Wpa2Personal,
+            AuthMethod::WPAWPA2Personal => WifiAuth::WpaWpa2Personal,
+            AuthMethod::WPA2Enterprise => WifiAuth::Wpa2Enterprise,
+            AuthMethod::WPA3Personal => WifiAuth::Wpa3Personal,
+            AuthMethod::WPA2WPA3Personal => WifiAuth::Wpa2Wpa3Personal,
+            AuthMethod::WAPIPersonal => WifiAuth::WapiPersonal,
+        }
+    }
+}
+
+impl From<WifiAuth> for AuthMethod {
+    fn from(auth_method: WifiAuth) -> Self {
+        match auth_method {
+            WifiAuth::None => AuthMethod::None,
+            WifiAuth::Wep => AuthMethod::WEP,
+            WifiAuth::Wpa => AuthMethod::WPA,
+            WifiAuth::Wpa2Personal => AuthMethod::WPA2Personal,
+            WifiAuth::WpaWpa2Personal => AuthMethod::WPAWPA2Personal,
+            WifiAuth::Wpa2Enterprise => AuthMethod::WPA2Enterprise,
+            WifiAuth::Wpa3Personal => AuthMethod::WPA3Personal,
+            WifiAuth::Wpa2Wpa3Personal => AuthMethod::WPA2WPA3Personal,
+            WifiAuth::WapiPersonal => AuthMethod::WAPIPersonal,
+        }
+    }
+}
+
+/// WPA3 requires protected management frames, WPA2 networks get them when
+/// the AP offers them.
+fn pmf_configuration(auth_method: AuthMethod) -> PmfConfiguration {
+    match auth_method {
+        AuthMethod::WPA3Personal => PmfConfiguration::Capable { required: true },
+        AuthMethod::WPA2Personal
+        | AuthMethod::WPAWPA2Personal
+        | AuthMethod::WPA2WPA3Personal
+        | AuthMethod::WPA2Enterprise => PmfConfiguration::Capable { required: false },
+        _ => PmfConfiguration::NotCapable,
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 struct Wifi {
     ssid: String,
+    #[serde(default)]
     password: String,
+    /// Detected from a scan when not set.
+    auth_method: Option<WifiAuth>,
+    ip: Option<ip::IpSettings>,
+    #[serde(default, with = "mac::optional")]
+    mac: Option<[u8; 6]>,
+}
+
+impl Wifi {
+    fn validate(&self) -> Result<()> {
+        if self.ssid.is_empty() || self.ssid.len() > 32 {
+            bail!("SSID 长度应为 1-32 字节");
+        }
+        let password = self.password.as_str();
+        let hex = password.bytes().all(|b| b.is_ascii_hexdigit());
+        match &self.auth_method {
+            _ if password.is_empty() => {}
+            Some(WifiAuth::None) => bail!("无密码网络不应填写密码"),
+            Some(WifiAuth::Wep) => {
+                if !(matches!(password.len(), 5 | 13) && password.is_ascii()
+                    || matches!(password.len(), 10 | 26) && hex)
+                {
+                    bail!("WEP 密码应为 5 或 13 个字符，或 10 或 26 位十六进制数");
+                }
+            }
+            _ => {
+                if !((8..=63).contains(&password.len()) && password.is_ascii()
+                    || password.len() == 64 && hex)
+                {
+                    bail!("密码应为 8-63 个 ASCII 字符，或 64 位十六进制数");
+                }
+            }
+        }
+        if let Some(mac) = &self.mac {
+            mac::validate_clone(mac)?;
+        }
+        match &self.ip {
+            Some(ip) => ip.validate(),
+            None => Ok(()),
+        }
+    }
 }
 
 impl fmt::Debug for Wifi {
@@ -115,47 +379,330 @@ impl fmt::Debug for Wifi {
         f.debug_struct("Wifi")
             .field("ssid", &self.ssid)
             .field("password", &hidden_password)
+            .field("auth_method", &self.auth_method)
+            .field("ip", &self.ip)
+            .field("mac", &self.mac)
             .finish()
     }
 }
 
+/// Stored in NVS with bincode, which goes by variant index, and taken as
+/// JSON by the API, which goes by the names.
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
 enum NetConfig {
     BuptPortal(bupt::BuptAccount),
     NormalWifi(Wifi),
+    Enterprise(enterprise::EnterpriseWifi),
+}
+
+impl NetConfig {
+    fn ssid(&self) -> &str {
+        match self {
+            NetConfig::BuptPortal(_) => "BUPT-portal",
+            NetConfig::NormalWifi(wifi) => &wifi.ssid,
+            NetConfig::Enterprise(wifi) => &wifi.ssid,
+        }
+    }
+
+    /// Static address and hostname, the campus portal always uses DHCP.
+    fn ip_settings(&self) -> Option<&ip::IpSettings> {
+        match self {
+            NetConfig::BuptPortal(_) => None,
+            NetConfig::NormalWifi(wifi) => wifi.ip.as_ref(),
+            NetConfig::Enterprise(wifi) => wifi.ip.as_ref(),
+        }
+    }
+
+    /// MAC to use instead of the one picked by the MAC policy.
+    fn cloned_mac(&self) -> Option<[u8; 6]> {
+        match self {
+            NetConfig::BuptPortal(account) => account.mac,
+            NetConfig::NormalWifi(wifi) => wifi.mac,
+            NetConfig::Enterprise(wifi) => wifi.mac,
+        }
+    }
+
+    /// Whether the auth method has to be detected from a scan.
+    fn needs_detection(&self) -> bool {
+        matches!(self, NetConfig::NormalWifi(wifi) if wifi.auth_method.is_none())
+    }
+
+    /// Builds the STA configuration. A stored auth method wins over the
+    /// `detected` one; without either, WPA2 is assumed unless there is no
+    /// password.
+    fn client_configuration(&self, detected: Option<AuthMethod>) -> ClientConfiguration {
+        let ssid = heapless::String::<32>::from_iter(self.ssid().chars());
+        match self {
+            NetConfig::BuptPortal(_) => ClientConfiguration {
+                ssid,
+                auth_method: AuthMethod::None,
+                ..Default::default()
+            },
+            NetConfig::NormalWifi(wifi) => {
+                let auth_method = wifi
+                    .auth_method
+                    .map(AuthMethod::from)
+                    .or(detected)
+                    .unwrap_or(if wifi.password.is_empty() {
+                        AuthMethod::None
+                    } else {
+                        AuthMethod::WPA2Personal
+                    });
+                ClientConfiguration {
+                    ssid,
+                    password: heapless::String::<64>::from_iter(wifi.password.chars()),
+                    channel: None,
+                    auth_method,
+                    pmf_cfg: pmf_configuration(auth_method),
+                    ..Default::default()
+                }
+            }
+            NetConfig::Enterprise(_) => ClientConfiguration {
+                ssid,
+                channel: None,
+                auth_method: AuthMethod::WPA2Enterprise,
+                pmf_cfg: pmf_configuration(AuthMethod::WPA2Enterprise),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match self {
+            NetConfig::BuptPortal(account) if account.username.is_empty() => {
+                bail!("学号不能为空")
+            }
+            NetConfig::BuptPortal(account) => match &account.mac {
+                Some(mac) => mac::validate_clone(mac),
+                None => Ok(()),
+            },
+            NetConfig::NormalWifi(wifi) => wifi.validate(),
+            NetConfig::Enterprise(wifi) => wifi.validate(),
+        }
+    }
+
+    /// Enables the EAP client for 802.1X networks and disables it otherwise.
+    fn setup_eap(&self) -> Result<()> {
+        match self {
+            NetConfig::Enterprise(wifi) => enterprise::enable(wifi),
+            _ => enterprise::disable(),
+        }
+    }
+}
+
+/// SSIDs operated by the campus network.
+const CAMPUS_SSIDS: &[&str] = &["BUPT-portal", "BUPT-mobile", "eduroam"];
+
+#[derive(serde::Serialize, Debug, Clone)]
+struct ScannedNetwork {
+    ssid: String,
+    rssi: i8,
+    channel: u8,
+    auth_method: Option<WifiAuth>,
+    campus: bool,
+}
+
+impl From<&AccessPointInfo> for ScannedNetwork {
+    fn from(ap: &AccessPointInfo) -> Self {
+        Self {
+            ssid: ap.ssid.to_string(),
+            rssi: ap.signal_strength,
+            channel: ap.channel,
+            auth_method: ap.auth_method.map(WifiAuth::from),
+            campus: CAMPUS_SSIDS.contains(&ap.ssid.as_str()),
+        }
+    }
+}
+
+/// Scans for nearby access points, keeping the strongest entry for every
+/// visible SSID, sorted by signal strength.
+fn scan(wifi: &mut BlockingWifi<&mut EspWifi<'static>>) -> Result<Vec<ScannedNetwork>> {
+    Ok(strongest(&wifi.scan()?))
+}
+
+/// Keeps the strongest entry for every visible SSID, sorted by signal
+/// strength.
+fn strongest(aps: &[AccessPointInfo]) -> Vec<ScannedNetwork> {
+    let mut networks: Vec<ScannedNetwork> = Vec::new();
+    for ap in aps.iter().filter(|ap| !ap.ssid.is_empty()) {
+        match networks.iter_mut().find(|n| n.ssid == ap.ssid.as_str()) {
+            Some(known) if known.rssi >= ap.signal_strength => {}
+            Some(known) => *known = ap.into(),
+            None => networks.push(ap.into()),
+        }
+    }
+    networks.sort_by(|a, b| b.rssi.cmp(&a.rssi));
+    networks
+}
+
+/// A saved network with the signal strength and auth method it was seen
+/// with, both `None` when it didn't show up in the scan.
+type Candidate = (saved::SavedNetwork, Option<i8>, Option<AuthMethod>);
+
+/// Ranks the saved networks seen in `scanned` by priority and then signal
+/// strength. Networks missing from the scan, e.g. with a hidden SSID, are
+/// still tried after the visible ones, as are all of them when the scan
+/// failed.
+fn rank(
+    networks: Vec<saved::SavedNetwork>,
+    scanned: Result<Vec<ScannedNetwork>>,
+) -> Vec<Candidate> {
+    let scanned = scanned.unwrap_or_else(|e| {
+        log::warn!("Failed to scan: {}, trying all saved networks", e);
+        Vec::new()
+    });
+    let mut candidates: Vec<Candidate> = networks
+        .into_iter()
+        .map(
+            |network| match scanned.iter().find(|s| s.ssid == network.config.ssid()) {
+                Some(ap) => (network, Some(ap.rssi), ap.auth_method.map(AuthMethod::from)),
+                None => (network, None, None),
+            },
+        )
+        .collect();
+    candidates.sort_by(|(a, a_rssi, _), (b, b_rssi, _)| {
+        b_rssi
+            .is_some()
+            .cmp(&a_rssi.is_some())
+            .then(b.priority.cmp(&a.priority))
+            .then(b_rssi.cmp(a_rssi))
+    });
+    candidates
+}
+
+/// Describes the signal of a [`Candidate`] for the logs.
+fn describe_rssi(rssi: Option<i8>) -> String {
+    match rssi {
+        Some(rssi) => format!("{} dBm", rssi),
+        None => "not seen in scan".to_string(),
+    }
+}
+
+/// Scans and joins the best saved network in range, ranked by priority and
+/// then signal strength. Falls back to the next one when a network can't be
+/// joined.
+fn connect_best(
+    networks: Vec<saved::SavedNetwork>,
+    esp_wifi: &mut EspWifi<'static>,
+    sysloop: EspSystemEventLoop,
+) -> Result<NetConfig> {
+    state::transition(NetState::Scanning);
+    let scanned = {
+        let mut wifi = BlockingWifi::wrap(&mut *esp_wifi, sysloop.clone())?;
+        if !wifi.is_started()? {
+            wifi.set_configuration(&Configuration::Client(Default::default()))?;
+            wifi.start()?;
+        }
+        scan(&mut wifi)
+    };
+
+    let candidates = rank(networks, scanned);
+
+    for (network, rssi, detected) in candidates {
+        log::info!(
+            "Trying {} (priority {}, {})",
+            network.config.ssid(),
+            network.priority,
+            describe_rssi(rssi)
+        );
+        match connect_wifi_with_config(&network.config, detected, esp_wifi, sysloop.clone()) {
+            Ok(_) => return Ok(network.config),
+            Err(e) => log::warn!("Failed to join {}: {}", network.config.ssid(), e),
+        }
+    }
+    bail!("No saved network could be joined")
 }
 
-#[cfg(feature = "random_mac")]
-pub fn generate_random_mac() -> [u8; 6] {
-    use rand::Rng;
-    let mut rng = rand::thread_rng();
-    let mut mac = [0u8; 6];
-    rng.fill(&mut mac);
-    mac[0] &= 0xFE;
-    mac[0] &= 0xFD;
-    mac
+/// A joined network and the driver connected to it.
+pub struct Connection {
+    wifi: Box<EspWifi<'static>>,
+    sysloop: EspSystemEventLoop,
+    config: NetConfig,
 }
 
-pub fn connect() -> Result<Box<EspWifi<'static>>> {
+pub fn connect() -> Result<Connection> {
     set_target_level("wifi", log::LevelFilter::Warn)?;
     set_target_level("wifi_init", log::LevelFilter::Warn)?;
 
     #[cfg(feature = "clean_nvs")]
-    crate::nvs::remove::<NetConfig>()?;
+    saved::clear()?;
+
+    let sysloop = EspSystemEventLoop::take()?;
+    let networks = saved::load_all()?;
+    if networks.is_empty() {
+        let p = provisioning::Provisioner::new(sysloop.clone())?;
+        let config = p.wait();
+        return provisioned(p, sysloop, config);
+    }
+
+    log::info!("Loaded {} saved networks: {:?}", networks.len(), &networks);
+    let mut wifi = create_wifi(Peripherals::take()?.modem, sysloop.clone())?;
+    let config = connect_best(networks, &mut wifi, sysloop.clone())?;
+    Ok(Connection {
+        wifi,
+        sysloop,
+        config,
+    })
+}
+
+/// Stops the provisioning portal once `config` has been provisioned with it.
+fn provisioned(
+    p: provisioning::Provisioner,
+    sysloop: EspSystemEventLoop,
+    config: NetConfig,
+) -> Result<Connection> {
+    let mut wifi = p.into_wifi()?;
+    // Keep the station connection, but shut down the provisioning AP, or turn
+    // it into the hotspot.
+    if let Configuration::Mixed(client, _) = wifi.get_configuration()? {
+        wifi.set_configuration(&match hotspot::ap_configuration()? {
+            Some(ap_config) => Configuration::Mixed(client, ap_config),
+            None => Configuration::Client(client),
+        })?;
+    }
+    state::transition(NetState::Online);
+    Ok(Connection {
+        wifi,
+        sysloop,
+        config,
+    })
+}
 
-    match crate::nvs::load::<NetConfig>()? {
-        Some(config) => {
-            log::info!("Loaded NetConfig: {:?}", &config);
-            connect_wifi_with_config(
-                config,
-                Peripherals::take()?.modem,
-                EspSystemEventLoop::take()?,
-            )
+/// Keeps the device online. Only returns if the supervisor can't be started.
+pub fn keep_alive(connection: Connection) -> Result<()> {
+    let _mdns = mdns::start()
+        .map_err(|e| log::warn!("Failed to start mDNS: {}", e))
+        .ok();
+    let options = supervisor::options()?;
+    log::info!("Starting supervisor: {:?}", &options);
+    let hotspot = if hotspot::config()?.enabled {
+        match hotspot::Hotspot::start(&connection.wifi) {
+            Ok(hotspot) => Some(hotspot),
+            Err(e) => {
+                log::warn!("Failed to start the hotspot: {}", e);
+                None
+            }
         }
-        None => {
-            let p = provisioning::Provisioner::new()?;
-            p.wait();
-            Ok(p.wifi)
+    } else {
+        None
+    };
+    let events = state::Events::subscribe(&connection.sysloop)?;
+    let admin = match admin::Admin::start(events.waker(), hotspot.is_some()) {
+        Ok(admin) => Some(admin),
+        Err(e) => {
+            log::warn!("Failed to start the admin page: {}", e);
+            None
         }
-    }
+    };
+    supervisor::Supervisor::new(
+        connection.wifi,
+        connection.sysloop,
+        events,
+        connection.config,
+        options,
+        hotspot,
+        admin,
+    )
+    .run()
 }
@@ -0,0 +1,295 @@
+//! The versioned JSON API, `/api/v1/*`. Successful responses are
+//! `{"code": 0, "data": ...}`, failures `{"code": <HTTP status>, "message": ...}`
+//! with the matching status, BUPT-portal login failures carry their `error`
+//! code as well.
+
+use std::{
+    net::Ipv4Addr,
+    sync::{Arc, Mutex},
+};
+
+use embedded_svc::http::Headers;
+use esp_idf_svc::{
+    eventloop::EspSystemEventLoop,
+    http::{
+        server::{EspHttpConnection, EspHttpServer, Request},
+        Method,
+    },
+    io::Write,
+    wifi::{BlockingWifi, EspWifi},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+
+use super::{finish, forward_dns, join_sta, Finished};
+use crate::net::{
+    auth, bupt, dns, saved,
+    state::{self, NetState},
+    web::{check_host_and_log, query_param, read_body_to_string},
+    NetConfig,
+};
+
+struct ApiError {
+    status: u16,
+    message: String,
+    /// [`bupt_portal::BuptError::code`] of a failed login.
+    error: Option<&'static str>,
+}
+
+impl ApiError {
+    fn new(status: u16, message: impl ToString) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+            error: None,
+        }
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// The routes registered below with their methods, to tell a wrong method
+/// (405) from an unknown path (404).
+const ROUTES: &[(&str, &[&str])] = &[
+    ("/api/v1/status", &["GET"]),
+    ("/api/v1/config", &["POST", "DELETE"]),
+    ("/api/v1/networks", &["GET"]),
+];
+
+/// Every method the catch-all answers, so none falls through to the
+/// frontend or to the server's HTML errors.
+const METHODS: [Method; 7] = [
+    Method::Get,
+    Method::Head,
+    Method::Post,
+    Method::Put,
+    Method::Delete,
+    Method::Options,
+    Method::Patch,
+];
+
+/// Body of `POST /api/v1/config`.
+#[derive(Deserialize)]
+struct ConfigRequest {
+    config: NetConfig,
+    /// Higher is preferred when several saved networks are in range.
+    #[serde(default)]
+    priority: u8,
+}
+
+/// Response of `GET /api/v1/status`.
+#[derive(Serialize)]
+struct Status {
+    state: NetState,
+    /// Whether a network has been provisioned since the portal started.
+    provisioned: bool,
+    /// The network the station is connected to.
+    ssid: Option<String>,
+    ip: Option<Ipv4Addr>,
+    /// Credentials are left out.
+    saved: Vec<saved::Summary>,
+}
+
+fn respond<T: Serialize>(
+    req: Request<&mut EspHttpConnection>,
+    result: ApiResult<T>,
+) -> anyhow::Result<()> {
+    respond_with_headers(req, result, &[])
+}
+
+fn respond_with_headers<T: Serialize>(
+    req: Request<&mut EspHttpConnection>,
+    result: ApiResult<T>,
+    headers: &[(&str, &str)],
+) -> anyhow::Result<()> {
+    let (status, body) = match result {
+        Ok(data) => (200, json!({"code": 0, "data": data})),
+        Err(e) => {
+            log::warn!("API error {}: {}", e.status, e.message);
+            let mut body = json!({"code": e.status, "message": e.message});
+            if let Some(error) = e.error {
+                body["error"] = error.into();
+            }
+            (e.status, body)
+        }
+    };
+    let headers: Vec<_> = [("Content-Type", "application/json")]
+        .into_iter()
+        .chain(headers.iter().copied())
+        .collect();
+    req.into_response(status, None, &headers)?
+        .write_all(body.to_string().as_bytes())?;
+    Ok(())
+}
+
+/// State-changing routes need an admin session, see [`auth`].
+fn require_admin(req: &Request<&mut EspHttpConnection>) -> ApiResult<()> {
+    match auth::is_authenticated(req) {
+        true => Ok(()),
+        false => Err(ApiError::new(401, auth::UNAUTHORIZED)),
+    }
+}
+
+fn read_json<T: DeserializeOwned>(req: &mut Request<&mut EspHttpConnection>) -> ApiResult<T> {
+    let is_json = req
+        .header("Content-Type")
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    if !is_json {
+        return Err(ApiError::new(415, "Content-Type 应为 application/json"));
+    }
+    let body = read_body_to_string(req).map_err(|e| ApiError::new(400, e))?;
+    serde_json::from_str(&body).map_err(|e| ApiError::new(400, format!("JSON 格式错误: {}", e)))
+}
+
+fn status(wifi: &Mutex<Box<EspWifi<'static>>>, finished: &Finished) -> ApiResult<Status> {
+    let internal = |e: anyhow::Error| ApiError::new(500, e);
+    let (ssid, ip) = {
+        let esp_wifi = wifi.lock().unwrap();
+        let connected = esp_wifi.is_connected().map_err(|e| internal(e.into()))?;
+        let ssid = match esp_wifi.get_configuration() {
+            Ok(configuration) if connected => configuration
+                .as_client_conf_ref()
+                .map(|client| client.ssid.to_string()),
+            _ => None,
+        };
+        let ip = match esp_wifi.sta_netif().is_up() {
+            Ok(true) => esp_wifi.sta_netif().get_ip_info().ok().map(|info| info.ip),
+            _ => None,
+        };
+        (ssid, ip)
+    };
+    Ok(Status {
+        state: state::current(),
+        provisioned: finished.0.lock().unwrap().is_some(),
+        ssid,
+        ip,
+        saved: saved::load_all()
+            .map_err(internal)?
+            .iter()
+            .map(saved::Summary::from)
+            .collect(),
+    })
+}
+
+/// Validates and joins the requested network, logging in for BUPT-portal.
+fn apply(
+    wifi: &Mutex<Box<EspWifi<'static>>>,
+    sys_loop: &EspSystemEventLoop,
+    request: &ConfigRequest,
+) -> ApiResult<()> {
+    let config = &request.config;
+    config.validate().map_err(|e| ApiError::new(400, e))?;
+    join_sta(wifi, sys_loop, config)
+        .and_then(|_| match config {
+            NetConfig::BuptPortal(account) => bupt::login(account),
+            _ => Ok(()),
+        })
+        .map_err(|e| ApiError {
+            error: bupt::classify(&e).map(|e| e.code()),
+            ..ApiError::new(502, e)
+        })
+}
+
+/// Answers requests no route took: 405 with the allowed methods for a known
+/// path, 404 otherwise.
+fn unmatched(req: Request<&mut EspHttpConnection>) -> anyhow::Result<()> {
+    let method = format!("{:?}", req.method()).to_uppercase();
+    let path = req.uri().split('?').next().unwrap_or_default().to_string();
+    match ROUTES.iter().find(|(route, _)| *route == path) {
+        Some((_, methods)) => {
+            let allow = methods.join(", ");
+            let message = format!("{} 不支持 {} 方法", path, method);
+            let headers = [("Allow", allow.as_str())];
+            respond_with_headers::<()>(req, Err(ApiError::new(405, message)), &headers)
+        }
+        None => {
+            let message = format!("未知的 API: {} {}", method, path);
+            respond::<()>(req, Err(ApiError::new(404, message)))
+        }
+    }
+}
+
+/// Forgets the network given by `?ssid=`.
+fn delete_config(uri: &str) -> ApiResult<()> {
+    let ssid = query_param(uri, "ssid").ok_or_else(|| ApiError::new(400, "缺少 ssid 参数"))?;
+    match saved::remove(&ssid).map_err(|e| ApiError::new(500, e))? {
+        true => Ok(()),
+        false => Err(ApiError::new(404, format!("未找到已保存的网络: {}", ssid))),
+    }
+}
+
+pub fn register(
+    http: &mut EspHttpServer<'static>,
+    wifi: &Arc<Mutex<Box<EspWifi<'static>>>>,
+    sys_loop: &EspSystemEventLoop,
+    dns: &Arc<dns::DnsServer>,
+    finished: &Arc<Finished>,
+) -> anyhow::Result<()> {
+    let wifi1 = Arc::clone(wifi);
+    let finished1 = Arc::clone(finished);
+    http.fn_handler::<anyhow::Error, _>("/api/v1/status", Method::Get, move |req| {
+        if let Some(req) = check_host_and_log(req)? {
+            let result = status(&wifi1, &finished1);
+            respond(req, result)?;
+        }
+        Ok(())
+    })?;
+
+    let wifi2 = Arc::clone(wifi);
+    let sys_loop2 = sys_loop.clone();
+    let dns2 = Arc::clone(dns);
+    let finished2 = Arc::clone(finished);
+    http.fn_handler::<anyhow::Error, _>("/api/v1/config", Method::Post, move |req| {
+        if let Some(mut req) = check_host_and_log(req)? {
+            let request =
+                match require_admin(&req).and_then(|_| read_json::<ConfigRequest>(&mut req)) {
+                    Ok(request) => request,
+                    Err(e) => return respond::<()>(req, Err(e)),
+                };
+            let result = apply(&wifi2, &sys_loop2, &request);
+            let joined = result.is_ok();
+            respond(req, result)?;
+            if joined {
+                forward_dns(&wifi2, &dns2);
+                finish(&finished2, request.config, request.priority)?;
+            }
+        }
+        Ok(())
+    })?;
+
+    http.fn_handler::<anyhow::Error, _>("/api/v1/config", Method::Delete, |req| {
+        if let Some(req) = check_host_and_log(req)? {
+            let result = require_admin(&req).and_then(|_| delete_config(req.uri()));
+            respond(req, result)?;
+        }
+        Ok(())
+    })?;
+
+    let wifi3 = Arc::clone(wifi);
+    let sys_loop3 = sys_loop.clone();
+    http.fn_handler::<anyhow::Error, _>("/api/v1/networks", Method::Get, move |req| {
+        if let Some(req) = check_host_and_log(req)? {
+            // Scanning takes the radio off the channel of the AP for a while.
+            let result = require_admin(&req).and_then(|_| {
+                let mut esp_wifi = wifi3.lock().unwrap();
+                BlockingWifi::wrap(&mut **esp_wifi, sys_loop3.clone())
+                    .map_err(anyhow::Error::from)
+                    .and_then(|mut wifi| crate::net::scan(&mut wifi))
+                    .map_err(|e| ApiError::new(500, format!("扫描失败: {}", e)))
+            });
+            respond(req, result)?;
+        }
+        Ok(())
+    })?;
+
+    for method in METHODS {
+        http.fn_handler::<anyhow::Error, _>("/api/v1/*", method, |req| {
+            if let Some(req) = check_host_and_log(req)? {
+                unmatched(req)?;
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,371 @@
+mod api;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+};
+
+use serde_json::json;
+
+use embedded_svc::http::Headers;
+
+use esp_idf_svc::{
+    eventloop::EspSystemEventLoop,
+    hal::prelude::Peripherals,
+    http::{server::EspHttpServer, Method},
+    io::Write,
+    mdns::EspMdns,
+    wifi::{
+        self, AccessPointConfiguration, AuthMethod, BlockingWifi, ClientConfiguration, EspWifi,
+    },
+};
+
+use log::*;
+
+use crate::net::{
+    ap,
+    auth::{self, check_admin},
+    bupt, captive, dns, forms, hotspot, ip as netip, mdns,
+    state::{self, NetState},
+    web::{self, check_host_and_log, parse_form, read_body_to_string},
+};
+
+const STACK_SIZE: usize = 10240;
+/// The routes here, the v1 API and the shared pages need more than the
+/// default of 32.
+const MAX_URI_HANDLERS: usize = 48;
+
+type Finished = (Mutex<Option<super::NetConfig>>, Condvar);
+
+fn finish(finished: &Finished, config: super::NetConfig, priority: u8) -> anyhow::Result<()> {
+    super::saved::save(super::saved::SavedNetwork {
+        config: config.clone(),
+        priority,
+    })
+    .map_err(|x| {
+        log::error!("Failed to save network: {:?} / {}", x, x);
+        x
+    })?;
+    let (lock, cvar) = finished;
+    *lock.lock().unwrap() = Some(config);
+    cvar.notify_all();
+    Ok(())
+}
+
+pub struct Provisioner {
+    wifi: Arc<Mutex<Box<EspWifi<'static>>>>,
+    finished: Arc<Finished>,
+    #[allow(dead_code)]
+    dns: Arc<dns::DnsServer>,
+    #[allow(dead_code)]
+    http: EspHttpServer<'static>,
+    #[allow(dead_code)]
+    mdns: Option<EspMdns>,
+}
+
+impl Provisioner {
+    pub fn new(sys_loop: EspSystemEventLoop) -> anyhow::Result<Self> {
+        let wifi = Arc::new(Mutex::new(setup_ap(sys_loop.clone())?));
+
+        let mut dns = dns::DnsServer::new(ap::IP);
+        dns.start()?;
+        let dns = Arc::new(dns);
+
+        let mut http = EspHttpServer::new(&esp_idf_svc::http::server::Configuration {
+            stack_size: STACK_SIZE,
+            uri_match_wildcard: true,
+            max_uri_handlers: MAX_URI_HANDLERS,
+            ..Default::default()
+        })?;
+
+        let finished = Arc::new((Mutex::new(None), Condvar::new()));
+        // The forms join the network, log in for BUPT-portal, and only save
+        // it once that worked.
+        let parsers: [(
+            &str,
+            fn(&mut HashMap<String, String>) -> anyhow::Result<super::NetConfig>,
+        ); 3] = [
+            ("/login", |form| {
+                Ok(super::NetConfig::BuptPortal(forms::bupt_account(form)?))
+            }),
+            ("/wifi", |form| {
+                Ok(super::NetConfig::NormalWifi(forms::wifi(form)?))
+            }),
+            ("/enterprise", |form| {
+                Ok(super::NetConfig::Enterprise(forms::enterprise(form)?))
+            }),
+        ];
+        for (path, parse) in parsers {
+            let wifi = Arc::clone(&wifi);
+            let sys_loop = sys_loop.clone();
+            let dns = Arc::clone(&dns);
+            let finished = Arc::clone(&finished);
+            http.fn_handler::<anyhow::Error, _>(path, Method::Post, move |req| {
+                if let Some(mut req) = check_admin(req)? {
+                    let body = read_body_to_string(&mut req)?;
+
+                    if req.header("Content-Type") == Some("application/x-www-form-urlencoded") {
+                        let result = parse_form(&body).and_then(|mut form| {
+                            let priority = forms::priority(&mut form)?.unwrap_or(0);
+                            let config = parse(&mut form)?;
+                            config.validate()?;
+                            join_sta(&wifi, &sys_loop, &config)?;
+                            if let super::NetConfig::BuptPortal(account) = &config {
+                                bupt::login(account)?;
+                            }
+                            Ok((config, priority))
+                        });
+                        match result {
+                            Ok((config, priority)) => {
+                                req.into_ok_response()?
+                                    .write_all(json!({"code": 0}).to_string().as_bytes())?;
+                                forward_dns(&wifi, &dns);
+                                finish(&finished, config, priority)?;
+                            }
+                            Err(e) => {
+                                let error = bupt::classify(&e).map(|e| e.code());
+                                req.into_ok_response()?.write_all(
+                                    json!({"code": 1, "message": e.to_string(), "error": error})
+                                        .to_string()
+                                        .as_bytes(),
+                                )?;
+                            }
+                        }
+                    } else {
+                        log::info!("Invalid Content-Type");
+                        req.into_response(400, None, &[])?;
+                    }
+                }
+                Ok(())
+            })?;
+        }
+
+        let wifi3 = Arc::clone(&wifi);
+        let sys_loop3 = sys_loop.clone();
+        http.fn_handler::<anyhow::Error, _>("/api/scan", Method::Get, move |req| {
+            if let Some(req) = check_admin(req)? {
+                let networks = {
+                    let mut esp_wifi = wifi3.lock().unwrap();
+                    let mut wifi = BlockingWifi::wrap(&mut **esp_wifi, sys_loop3.clone())?;
+                    super::scan(&mut wifi)
+                };
+                match networks {
+                    Ok(networks) => {
+                        req.into_response(200, None, &[("Content-Type", "application/json")])?
+                            .write_all(serde_json::to_string(&networks)?.as_bytes())?;
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to scan: {}", e);
+                        req.into_response(500, None, &[])?;
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        http.fn_handler::<anyhow::Error, _>("/api/mac", Method::Get, |req| {
+            if let Some(req) = check_host_and_log(req)? {
+                let policy = super::mac::policy()?;
+                req.into_response(200, None, &[("Content-Type", "application/json")])?
+                    .write_all(json!({ "policy": policy }).to_string().as_bytes())?;
+            }
+            Ok(())
+        })?;
+
+        http.fn_handler::<anyhow::Error, _>("/mac", Method::Post, |req| {
+            if let Some(mut req) = check_admin(req)? {
+                let body = read_body_to_string(&mut req)?;
+
+                if req.header("Content-Type") == Some("application/x-www-form-urlencoded") {
+                    let mut form = parse_form(&body)?;
+                    let result = form
+                        .remove("policy")
+                        .ok_or(anyhow::anyhow!("Missing policy"))
+                        .and_then(|policy| super::mac::MacPolicy::from_name(&policy))
+                        .and_then(super::mac::set_policy);
+                    let response = match result {
+                        Ok(_) => json!({"code": 0}),
+                        Err(e) => json!({"code": 1, "message": e.to_string()}),
+                    };
+                    req.into_ok_response()?
+                        .write_all(response.to_string().as_bytes())?;
+                } else {
+                    log::info!("Invalid Content-Type");
+                    req.into_response(400, None, &[])?;
+                }
+            }
+            Ok(())
+        })?;
+
+        auth::register(&mut http, "provisioning")?;
+        api::register(&mut http, &wifi, &sys_loop, &dns, &finished)?;
+        hotspot::register(&mut http, false)?;
+        let finished5 = Arc::clone(&finished);
+        captive::register(&mut http, move || finished5.0.lock().unwrap().is_some())?;
+        web::serve_frontend(&mut http)?;
+
+        let ssid = ap::ssid()?;
+        match ap::passphrase()? {
+            passphrase if passphrase.is_empty() => log::info!("Wi-Fi `{}` is open", ssid),
+            passphrase => log::info!("Wi-Fi `{}` passphrase: {}", ssid, passphrase),
+        }
+        log::info!("Now visit http://{} to login", ap::IP);
+        log::info!("Admin PIN: {}", auth::pin()?);
+        let mdns = mdns::start()
+            .map_err(|e| log::warn!("Failed to start mDNS: {}", e))
+            .ok();
+
+        Ok(Self {
+            wifi,
+            dns,
+            finished,
+            http,
+            mdns,
+        })
+    }
+
+    /// Blocks until a network has been provisioned and saved, and returns it.
+    pub fn wait(&self) -> super::NetConfig {
+        let (finished, cvar) = &*self.finished;
+        let finished = cvar
+            .wait_while(finished.lock().unwrap(), |config| config.is_none())
+            .unwrap();
+        finished.clone().unwrap()
+    }
+
+    /// The provisioned network, if there is one yet. For waiting without
+    /// blocking, see [`Self::wait`].
+    #[cfg(feature = "embassy")]
+    pub fn finished(&self) -> Option<super::NetConfig> {
+        self.finished.0.lock().unwrap().clone()
+    }
+
+    /// Stops the portal and hands back the Wi-Fi driver, still connected with
+    /// the configuration that was just provisioned.
+    pub fn into_wifi(self) -> anyhow::Result<Box<EspWifi<'static>>> {
+        let Self { wifi, http, .. } = self;
+        // The handlers hold the remaining references to the driver.
+        drop(http);
+        Arc::try_unwrap(wifi)
+            .map_err(|_| anyhow::anyhow!("Wi-Fi driver is still in use"))?
+            .into_inner()
+            .map_err(|_| anyhow::anyhow!("Wi-Fi driver mutex poisoned"))
+    }
+}
+
+/// Lets the DNS server forward to the resolvers the STA side got, now that it
+/// is online.
+fn forward_dns(wifi: &Mutex<Box<EspWifi<'static>>>, dns: &dns::DnsServer) {
+    hotspot::forward_dns(&wifi.lock().unwrap(), dns);
+}
+
+fn bupt_portal_configuration() -> ClientConfiguration {
+    ClientConfiguration {
+        ssid: heapless::String::<32>::try_from("BUPT-portal").unwrap(),
+        auth_method: AuthMethod::None,
+        ..Default::default()
+    }
+}
+
+fn ap_configuration() -> anyhow::Result<AccessPointConfiguration> {
+    ap::configuration(&ap::passphrase()?)
+}
+
+/// Switches the STA side of the mixed-mode driver to `config` and waits for
+/// the netif to come up, leaving the provisioning AP configuration untouched.
+///
+/// The AP follows the STA channel, so clients may briefly lose the portal
+/// while the station is associating.
+fn join_sta(
+    wifi: &Mutex<Box<EspWifi<'static>>>,
+    sys_loop: &EspSystemEventLoop,
+    config: &super::NetConfig,
+) -> anyhow::Result<()> {
+    let mut esp_wifi = wifi.lock().unwrap();
+    let events = state::Events::subscribe(sys_loop)?;
+    let mut wifi = BlockingWifi::wrap(&mut **esp_wifi, sys_loop.clone())?;
+
+    let detected = if config.needs_detection() {
+        super::scan(&mut wifi)?
+            .into_iter()
+            .find(|network| network.ssid == config.ssid())
+            .and_then(|network| network.auth_method)
+            .map(AuthMethod::from)
+    } else {
+        None
+    };
+    let client = config.client_configuration(detected);
+
+    // Always reconnect, so that a changed password is tried even when
+    // already on the same network.
+    if wifi.is_connected()? {
+        super::disconnect(wifi.wifi_mut(), &events)?;
+    }
+    config.setup_eap()?;
+    netip::apply(wifi.wifi_mut(), config.ip_settings())?;
+
+    info!("Joining `{}` for provisioning...", client.ssid);
+    let ssid = client.ssid.clone();
+    wifi.set_configuration(&wifi::Configuration::Mixed(client, ap_configuration()?))?;
+    super::mac::apply(wifi.wifi_mut(), &ssid, config.cloned_mac())?;
+    if !wifi.is_started()? {
+        wifi.start()?;
+    }
+    let result = super::associate(wifi.wifi_mut(), &events, 1)
+        .map_err(|e| anyhow::anyhow!("Failed to connect wifi: {}", e));
+    state::transition(NetState::Idle);
+    result
+}
+
+fn setup_ap(sys_loop: EspSystemEventLoop) -> anyhow::Result<Box<EspWifi<'static>>> {
+    let mut esp_wifi = super::create_wifi(Peripherals::take()?.modem, sys_loop.clone())?;
+
+    let events = state::Events::subscribe(&sys_loop)?;
+    let mut wifi = BlockingWifi::wrap(&mut *esp_wifi, sys_loop)?;
+
+    let wifi_configuration =
+        wifi::Configuration::Mixed(bupt_portal_configuration(), ap_configuration()?);
+    wifi.set_configuration(&wifi_configuration)?;
+    super::mac::apply(wifi.wifi_mut(), "BUPT-portal", None)?;
+    wifi.start()?;
+    info!("Created Wi-Fi with WIFI_SSID `{}`", ap::ssid()?);
+
+    state::transition(NetState::Scanning);
+    let portal_in_range = match super::scan(&mut wifi) {
+        Ok(networks) => {
+            // Stay on the channel of BUPT-portal, which the AP has to follow
+            // once joined, otherwise move away from the crowd.
+            let portal = networks.iter().find(|n| n.ssid == "BUPT-portal");
+            let channel = match portal {
+                Some(portal) => portal.channel,
+                None => ap::quietest_channel(&networks),
+            };
+            if channel != ap::channel() {
+                ap::set_channel(channel);
+                wifi.set_configuration(&wifi::Configuration::Mixed(
+                    bupt_portal_configuration(),
+                    ap_configuration()?,
+                ))?;
+            }
+            portal.is_some()
+        }
+        Err(e) => {
+            log::warn!("Failed to scan: {}", e);
+            true
+        }
+    };
+    if !portal_in_range {
+        log::warn!("BUPT-portal is not in range");
+        state::transition(NetState::Idle);
+        return Ok(esp_wifi);
+    }
+
+    let result = super::associate(wifi.wifi_mut(), &events, super::ASSOCIATE_ATTEMPTS);
+    state::transition(NetState::Idle);
+    match result {
+        Ok(_) => log::info!("Connected to BUPT-portal"),
+        Err(e) => log::warn!("BUPT-portal is not reachable: {}", e),
+    }
+
+    Ok(esp_wifi)
+}
@@ -0,0 +1,149 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+
+use super::NetConfig;
+
+mod legacy;
+
+/// Number of NVS slots for saved networks.
+const MAX_NETWORKS: usize = 8;
+
+/// Whether the network of older firmware has been moved over since boot.
+static MIGRATED: AtomicBool = AtomicBool::new(false);
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SavedNetwork {
+    pub config: NetConfig,
+    /// Higher is preferred, ties are broken by signal strength.
+    pub priority: u8,
+}
+
+/// What clients get to see of a saved network, without the credentials.
+#[derive(serde::Serialize)]
+pub struct Summary {
+    ssid: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    priority: u8,
+}
+
+impl From<&SavedNetwork> for Summary {
+    fn from(network: &SavedNetwork) -> Self {
+        Self {
+            ssid: network.config.ssid().to_string(),
+            kind: match network.config {
+                NetConfig::BuptPortal(_) => "bupt_portal",
+                NetConfig::NormalWifi(_) => "normal_wifi",
+                NetConfig::Enterprise(_) => "enterprise",
+            },
+            priority: network.priority,
+        }
+    }
+}
+
+/// What goes into NVS, tagged so a later layout can still read this one.
+/// Before changing `SavedNetwork` or anything in it, freeze the current
+/// layout in [`legacy`], point `V1` at the frozen copy and add a `V2`.
+#[derive(serde::Serialize, serde::Deserialize)]
+enum Stored {
+    V1(SavedNetwork),
+}
+
+impl From<Stored> for SavedNetwork {
+    fn from(stored: Stored) -> Self {
+        match stored {
+            Stored::V1(network) => network,
+        }
+    }
+}
+
+fn key(slot: usize) -> String {
+    format!("network{}", slot)
+}
+
+fn load_slots() -> Result<Vec<Option<SavedNetwork>>> {
+    if !MIGRATED.swap(true, Ordering::Relaxed) {
+        if let Err(e) = migrate() {
+            MIGRATED.store(false, Ordering::Relaxed);
+            return Err(e);
+        }
+    }
+    read_slots()
+}
+
+fn read_slots() -> Result<Vec<Option<SavedNetwork>>> {
+    (0..MAX_NETWORKS)
+        .map(|slot| Ok(crate::nvs::load_from::<Stored>(&key(slot))?.map(Into::into)))
+        .collect()
+}
+
+/// Moves the single `NetConfig` of the first firmware into the slots.
+fn migrate() -> Result<()> {
+    if let Some(blob) = crate::nvs::load_raw::<NetConfig>()? {
+        match legacy::net_config(&blob) {
+            Some(config) => {
+                log::info!("Migrating saved NetConfig: {:?}", &config);
+                store(SavedNetwork {
+                    config,
+                    priority: 0,
+                })?;
+            }
+            None => log::warn!("Dropping unreadable saved NetConfig"),
+        }
+        crate::nvs::remove::<NetConfig>()?;
+    }
+    Ok(())
+}
+
+/// Loads every saved network.
+pub fn load_all() -> Result<Vec<SavedNetwork>> {
+    Ok(load_slots()?.into_iter().flatten().collect())
+}
+
+/// Saves `network`, replacing the entry with the same SSID. When all slots
+/// are taken, the entry with the lowest priority is dropped.
+pub fn save(network: SavedNetwork) -> Result<()> {
+    load_slots()?;
+    store(network)
+}
+
+fn store(network: SavedNetwork) -> Result<()> {
+    let slots = read_slots()?;
+    let slot = slots
+        .iter()
+        .position(|saved| {
+            saved
+                .as_ref()
+                .is_some_and(|saved| saved.config.ssid() == network.config.ssid())
+        })
+        .or_else(|| slots.iter().position(Option::is_none))
+        .unwrap_or_else(|| {
+            slots
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, saved)| saved.as_ref().map(|saved| saved.priority))
+                .map(|(slot, _)| slot)
+                .unwrap_or(0)
+        });
+    log::info!("Saving network {} to slot {}", network.config.ssid(), slot);
+    crate::nvs::save_to(Stored::V1(network), &key(slot))
+}
+
+/// Removes the network with `ssid`, returns whether it was saved.
+pub fn remove(ssid: &str) -> Result<bool> {
+    for (slot, saved) in load_slots()?.into_iter().enumerate() {
+        if saved.is_some_and(|saved| saved.config.ssid() == ssid) {
+            return crate::nvs::remove_from::<Stored>(&key(slot));
+        }
+    }
+    Ok(false)
+}
+
+pub fn clear() -> Result<()> {
+    for slot in 0..MAX_NETWORKS {
+        crate::nvs::remove_from::<Stored>(&key(slot))?;
+    }
+    crate::nvs::remove::<NetConfig>()?;
+    Ok(())
+}
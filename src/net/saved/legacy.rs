@@ -0,0 +1,66 @@
+//! The layout the first firmware stored its single network in. bincode goes
+//! by field order only, so a blob can only be read back with the exact struct
+//! it was written with. These are frozen copies, don't change them.
+
+use bincode::Options;
+
+use crate::net::{bupt::BuptAccount, NetConfig, Wifi};
+
+#[derive(serde::Deserialize)]
+struct Account {
+    username: String,
+    password: String,
+}
+
+#[derive(serde::Deserialize)]
+struct WifiV0 {
+    ssid: String,
+    password: String,
+}
+
+#[derive(serde::Deserialize)]
+enum ConfigV0 {
+    BuptPortal(Account),
+    NormalWifi(WifiV0),
+}
+
+impl From<Account> for BuptAccount {
+    fn from(account: Account) -> Self {
+        Self {
+            username: account.username,
+            password: account.password,
+            mac: None,
+        }
+    }
+}
+
+impl From<WifiV0> for Wifi {
+    fn from(wifi: WifiV0) -> Self {
+        Self {
+            ssid: wifi.ssid,
+            password: wifi.password,
+            auth_method: None,
+            ip: None,
+            mac: None,
+        }
+    }
+}
+
+impl From<ConfigV0> for NetConfig {
+    fn from(config: ConfigV0) -> Self {
+        match config {
+            ConfigV0::BuptPortal(account) => NetConfig::BuptPortal(account.into()),
+            ConfigV0::NormalWifi(wifi) => NetConfig::NormalWifi(wifi.into()),
+        }
+    }
+}
+
+/// Reads the single network the first firmware stored. The whole blob has to
+/// be consumed, `bincode::deserialize` would accept trailing bytes.
+pub fn net_config(blob: &[u8]) -> Option<NetConfig> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .deserialize::<ConfigV0>(blob)
+        .ok()
+        .map(Into::into)
+}
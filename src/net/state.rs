@@ -0,0 +1,194 @@
+use anyhow::Result;
+use esp_idf_svc::{
+    eventloop::{EspSubscription, EspSystemEventLoop, System},
+    netif::IpEvent,
+    sys,
+    wifi::WifiEvent,
+};
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Where the device is on its way online.
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NetState {
+    Idle,
+    Scanning,
+    Associating,
+    DhcpWait,
+    PortalCheck,
+    PortalLogin,
+    Online,
+    /// Waiting before the next attempt after a failure.
+    Backoff,
+}
+
+type Subscriber = Arc<dyn Fn(NetState, NetState) + Send + Sync>;
+
+struct Machine {
+    state: NetState,
+    subscribers: Vec<Subscriber>,
+}
+
+static MACHINE: Mutex<Machine> = Mutex::new(Machine {
+    state: NetState::Idle,
+    subscribers: Vec::new(),
+});
+
+pub fn current() -> NetState {
+    MACHINE.lock().unwrap().state
+}
+
+/// Calls `subscriber` with the old and the new state on every transition.
+pub fn subscribe(subscriber: impl Fn(NetState, NetState) + Send + Sync + 'static) {
+    MACHINE
+        .lock()
+        .unwrap()
+        .subscribers
+        .push(Arc::new(subscriber));
+}
+
+pub fn transition(to: NetState) {
+    let (from, subscribers) = {
+        let mut machine = MACHINE.lock().unwrap();
+        let from = machine.state;
+        if from == to {
+            return;
+        }
+        machine.state = to;
+        (from, machine.subscribers.clone())
+    };
+    log::info!("Net state: {:?} -> {:?}", from, to);
+    // Called without the lock, so subscribers may look at the state.
+    for subscriber in subscribers {
+        subscriber(from, to);
+    }
+}
+
+/// Station events the state machine reacts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetEvent {
+    Connected,
+    Disconnected,
+    GotIp,
+    LostIp,
+    /// Not from the driver: sent through a [`Waker`].
+    Requested,
+}
+
+/// Station events from the system event loop, queued until they are waited
+/// for. Unsubscribes when dropped.
+pub struct Events {
+    tx: mpsc::Sender<NetEvent>,
+    rx: mpsc::Receiver<NetEvent>,
+    _wifi: EspSubscription<'static, System>,
+    _ip: EspSubscription<'static, System>,
+}
+
+impl Events {
+    pub fn subscribe(sysloop: &EspSystemEventLoop) -> Result<Self> {
+        let (tx, rx) = mpsc::channel();
+
+        let wifi_tx = tx.clone();
+        let wifi = sysloop.subscribe::<WifiEvent, _>(move |event| {
+            let event = match event {
+                WifiEvent::StaConnected { .. } => NetEvent::Connected,
+                WifiEvent::StaDisconnected { .. } => NetEvent::Disconnected,
+                _ => return,
+            };
+            let _ = wifi_tx.send(event);
+        })?;
+
+        let ip_tx = tx.clone();
+        let ip = sysloop.subscribe::<IpEvent, _>(move |event| {
+            let event = match event {
+                IpEvent::DhcpIpAssigned { .. } => NetEvent::GotIp,
+                IpEvent::DhcpIpDeassigned { .. } => NetEvent::LostIp,
+                _ => return,
+            };
+            let _ = ip_tx.send(event);
+        })?;
+
+        Ok(Self {
+            tx,
+            rx,
+            _wifi: wifi,
+            _ip: ip,
+        })
+    }
+
+    /// Lets another thread interrupt [`Events::wait`].
+    pub fn waker(&self) -> Waker {
+        Waker(self.tx.clone())
+    }
+
+    /// Drops the events received so far.
+    pub fn clear(&self) {
+        while self.rx.try_recv().is_ok() {}
+    }
+
+    /// Waits for the next event until `deadline`. Returns `None` once it has
+    /// passed.
+    pub fn next(&self, deadline: Instant) -> Option<NetEvent> {
+        let remaining = deadline.checked_duration_since(Instant::now())?;
+        self.rx.recv_timeout(remaining).ok()
+    }
+
+    /// Waits for one of `wanted`, skipping other events. Returns `None` on
+    /// timeout.
+    pub fn wait(&self, timeout: Duration, wanted: &[NetEvent]) -> Option<NetEvent> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.next(deadline)? {
+                event if wanted.contains(&event) => return Some(event),
+                event => log::debug!("Ignoring {:?}", event),
+            }
+        }
+    }
+}
+
+/// Sends [`NetEvent::Requested`] to the [`Events`] it came from.
+#[derive(Clone)]
+pub struct Waker(mpsc::Sender<NetEvent>);
+
+impl Waker {
+    pub fn wake(&self) {
+        let _ = self.0.send(NetEvent::Requested);
+    }
+}
+
+/// Exponential backoff with jitter, so devices that lost the network at the
+/// same time don't retry in lockstep.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    current: Option<Duration>,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+            current: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+
+    /// Doubles the delay up to `max` and spreads it by ±25%.
+    pub fn next(&mut self) -> Duration {
+        let current = match self.current {
+            Some(current) => (current * 2).min(self.max),
+            None => self.min,
+        };
+        self.current = Some(current);
+        let jitter = 750 + unsafe { sys::esp_random() } % 501;
+        current * jitter / 1000
+    }
+}
@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use esp_idf_svc::{eventloop::EspSystemEventLoop, wifi::EspWifi};
+
+use super::{
+    admin::Admin,
+    hotspot::Hotspot,
+    saved,
+    state::{self, Backoff, Events, NetEvent, NetState},
+    NetConfig,
+};
+
+/// Shortest delay between two connectivity checks, each one costs a request
+/// to the portal.
+const MIN_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Set on the admin page, in seconds in JSON.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct SupervisorOptions {
+    /// Delay between two connectivity checks while online.
+    #[serde(with = "secs")]
+    pub check_interval: Duration,
+    /// First delay after a failed reconnect or re-login.
+    #[serde(with = "secs")]
+    pub min_backoff: Duration,
+    /// Upper bound of the exponential backoff.
+    #[serde(with = "secs")]
+    pub max_backoff: Duration,
+}
+
+impl Default for SupervisorOptions {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_secs(60),
+            min_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+impl SupervisorOptions {
+    pub fn validate(&self) -> Result<()> {
+        if self.check_interval < MIN_CHECK_INTERVAL {
+            bail!("检查间隔不能少于 {} 秒", MIN_CHECK_INTERVAL.as_secs());
+        }
+        if self.min_backoff.is_zero() {
+            bail!("最短重试间隔不能为 0");
+        }
+        if self.min_backoff > self.max_backoff {
+            bail!("最短重试间隔不能大于最长重试间隔");
+        }
+        Ok(())
+    }
+}
+
+/// Durations as whole seconds for the admin page, as they are in NVS.
+mod secs {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            duration.as_secs().serialize(serializer)
+        } else {
+            duration.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        if deserializer.is_human_readable() {
+            u64::deserialize(deserializer).map(Duration::from_secs)
+        } else {
+            Duration::deserialize(deserializer)
+        }
+    }
+}
+
+pub fn options() -> Result<SupervisorOptions> {
+    Ok(crate::nvs::load::<SupervisorOptions>()?.unwrap_or_default())
+}
+
+/// Saves options set on the admin page, `None` restores the defaults. The
+/// supervisor picks them up before its next check.
+pub fn set_options(options: Option<SupervisorOptions>) -> Result<()> {
+    match options {
+        Some(options) => {
+            options.validate()?;
+            log::info!("Setting supervisor options: {:?}", options);
+            crate::nvs::save(options)
+        }
+        None => crate::nvs::remove::<SupervisorOptions>().map(|_| ()),
+    }
+}
+
+/// Periodically checks the connection and brings it back when the Wi-Fi
+/// association is lost or the BUPT-portal session expires. A lost network may
+/// be replaced by another saved one.
+///
+/// While online, a disconnect event triggers the check right away instead
+/// of at the next interval. Networks saved on the admin page are switched to
+/// as soon as they arrive.
+pub struct Supervisor {
+    wifi: Box<EspWifi<'static>>,
+    sysloop: EspSystemEventLoop,
+    events: Events,
+    config: NetConfig,
+    options: SupervisorOptions,
+    backoff: Backoff,
+    hotspot: Option<Hotspot>,
+    admin: Option<Admin>,
+}
+
+impl Supervisor {
+    /// `events` has to be subscribed before the admin page is started with
+    /// its [`Events::waker`].
+    pub fn new(
+        wifi: Box<EspWifi<'static>>,
+        sysloop: EspSystemEventLoop,
+        events: Events,
+        config: NetConfig,
+        options: SupervisorOptions,
+        hotspot: Option<Hotspot>,
+        admin: Option<Admin>,
+    ) -> Self {
+        Self {
+            wifi,
+            sysloop,
+            events,
+            config,
+            backoff: Backoff::new(options.min_backoff, options.max_backoff),
+            options,
+            hotspot,
+            admin,
+        }
+    }
+
+    pub fn run(mut self) -> Result<()> {
+        loop {
+            self.reload_options();
+            let result = match self.admin.as_ref().and_then(Admin::take_request) {
+                Some(network) => self.switch(network),
+                None => self.tick(),
+            };
+            if let Some(admin) = &self.admin {
+                admin.update(&self.wifi, &self.config, &result);
+            }
+            match result {
+                Ok(_) => {
+                    self.backoff.reset();
+                    state::transition(NetState::Online);
+                    if let Some(hotspot) = &self.hotspot {
+                        hotspot.refresh(&self.wifi);
+                    }
+                    self.events.clear();
+                    // A request that came in before the clear would not wake
+                    // the wait.
+                    if self.admin.as_ref().is_some_and(Admin::has_request) {
+                        continue;
+                    }
+                    let wanted = [
+                        NetEvent::Disconnected,
+                        NetEvent::LostIp,
+                        NetEvent::Requested,
+                    ];
+                    match self.events.wait(self.options.check_interval, &wanted) {
+                        Some(NetEvent::Requested) => {}
+                        Some(event) => log::warn!("{:?}, checking the connection now", event),
+                        None => {}
+                    }
+                }
+                Err(e) => {
+                    let backoff = self.backoff.next();
+                    log::warn!("{}, will retry after {:?}", e, backoff);
+                    state::transition(NetState::Backoff);
+                    if let Some(hotspot) = &self.hotspot {
+                        hotspot.captive();
+                    }
+                    // New credentials end the wait early.
+                    self.events.wait(backoff, &[NetEvent::Requested]);
+                }
+            }
+        }
+    }
+
+    /// Takes options changed on the admin page. The backoff starts over with
+    /// the new bounds.
+    fn reload_options(&mut self) {
+        match options() {
+            Ok(options) if options != self.options => {
+                log::info!("Supervisor options changed: {:?}", options);
+                self.backoff = Backoff::new(options.min_backoff, options.max_backoff);
+                self.options = options;
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Failed to load supervisor options: {}", e),
+        }
+    }
+
+    /// Joins a network saved on the admin page.
+    fn switch(&mut self, network: saved::SavedNetwork) -> Result<()> {
+        log::info!("Switching to {} as requested", network.config.ssid());
+        self.config = super::connect_best(vec![network], &mut self.wifi, self.sysloop.clone())?;
+        Ok(())
+    }
+
+    fn tick(&mut self) -> Result<()> {
+        if !self.wifi.is_connected()? || !self.wifi.sta_netif().is_up()? {
+            log::warn!("Wifi disconnected, reconnecting...");
+            let networks = saved::load_all()?;
+            if networks.is_empty() {
+                bail!("No saved network");
+            }
+            self.config = super::connect_best(networks, &mut self.wifi, self.sysloop.clone())?;
+            return Ok(());
+        }
+
+        if let NetConfig::BuptPortal(account) = &self.config {
+            super::ensure_portal_session(account)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+//! Helpers shared by the HTTP servers of the device.
+
+use std::{collections::HashMap, net::Ipv4Addr};
+
+use embedded_svc::http::Headers;
+
+use esp_idf_svc::{
+    http::{
+        server::{EspHttpConnection, EspHttpServer, Request},
+        Method,
+    },
+    io::Write,
+};
+
+use include_dir::{include_dir, Dir};
+
+use super::ap::{self, IP_STRING};
+
+static FRONTEND: Dir = include_dir!("$OUT_DIR/frontend");
+
+include!(concat!(env!("OUT_DIR"), "/mime.rs"));
+
+pub fn read_body_to_string(req: &mut Request<&mut EspHttpConnection>) -> anyhow::Result<String> {
+    let mut body = Vec::new();
+    let mut buffer = [0; 4096];
+
+    loop {
+        let bytes_read = req.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        body.extend_from_slice(&buffer[..bytes_read]);
+    }
+
+    Ok(String::from_utf8(body)?)
+}
+
+pub fn parse_form(body: &str) -> anyhow::Result<HashMap<String, String>> {
+    let mut form = HashMap::new();
+    for pair in body.split('&') {
+        let mut pair = pair.split('=');
+        let key = pair.next().ok_or(anyhow::anyhow!("Invalid body"))?;
+        let value = pair.next().ok_or(anyhow::anyhow!("Invalid body"))?;
+        form.insert(
+            urlencoding::decode(key)?.to_string(),
+            urlencoding::decode(value)?.to_string(),
+        );
+    }
+    Ok(form)
+}
+
+/// Decodes the query parameter `key` of `uri`.
+pub fn query_param(uri: &str, key: &str) -> Option<String> {
+    let (_, query) = uri.split_once('?')?;
+    query.split('&').find_map(|pair| {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        if urlencoding::decode(name).ok()? != key {
+            return None;
+        }
+        urlencoding::decode(value)
+            .ok()
+            .map(|value| value.into_owned())
+    })
+}
+
+/// Takes a form field, treating an empty value as missing.
+pub fn take_field(form: &mut HashMap<String, String>, key: &str) -> Option<String> {
+    form.remove(key).filter(|value| !value.trim().is_empty())
+}
+
+/// Whether `host` addresses the device: an IP address, which the clients of
+/// either interface use, or the mDNS name.
+fn is_own_host(host: &str) -> bool {
+    let name = host.rsplit_once(':').map_or(host, |(name, _)| name);
+    name.parse::<Ipv4Addr>().is_ok()
+        || ap::hostname().is_ok_and(|hostname| {
+            name.strip_suffix(".local")
+                .is_some_and(|name| name.eq_ignore_ascii_case(&hostname))
+        })
+}
+
+/// Logs the request and redirects it to the portal unless it addresses the
+/// device, so pages requested by name land on the portal.
+pub fn check_host_and_log<'a, 'b>(
+    req: Request<&'a mut EspHttpConnection<'b>>,
+) -> anyhow::Result<Option<Request<&'a mut EspHttpConnection<'b>>>> {
+    log::info!(
+        "HTTP {:?} - {}{}",
+        req.method(),
+        req.host().unwrap_or("Unknown"),
+        req.uri()
+    );
+    if !req.host().is_some_and(is_own_host) {
+        req.into_response(
+            302,
+            None,
+            &[("Location", format!("http://{}/", IP_STRING).as_str())],
+        )?;
+        return Ok(None);
+    }
+    Ok(Some(req))
+}
+
+/// Serves the frontend, `/` and every other GET not handled before. Has to be
+/// registered last.
+pub fn serve_frontend(http: &mut EspHttpServer<'static>) -> anyhow::Result<()> {
+    http.fn_handler::<anyhow::Error, _>("/", Method::Get, |req| {
+        if let Some(req) = check_host_and_log(req)? {
+            match FRONTEND.get_file("index.html") {
+                Some(file) => {
+                    req.into_response(
+                        200,
+                        None,
+                        &[("Content-Type", "text/html"), ("Content-Encoding", "gzip")],
+                    )?
+                    .write_all(file.contents())?;
+                }
+                None => {
+                    req.into_response(404, None, &[])?;
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    http.fn_handler::<anyhow::Error, _>("*", Method::Get, |req| {
+        if let Some(req) = check_host_and_log(req)? {
+            match FRONTEND.get_file(req.uri().trim_start_matches('/')) {
+                Some(file) => {
+                    let ext = req.uri().split('.').last().unwrap_or("");
+                    let mime = MIME_TYPES
+                        .iter()
+                        .find(|(ext_, _)| ext == *ext_)
+                        .map(|(_, mime)| *mime)
+                        .unwrap_or("application/octet-stream");
+                    req.into_response(
+                        200,
+                        None,
+                        &[("Content-Type", mime), ("Content-Encoding", "gzip")],
+                    )?
+                    .write_all(file.contents())?;
+                }
+                None => {
+                    req.into_response(404, None, &[])?;
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(())
+}
@@ -35,22 +35,15 @@ where
     Ok(())
 }
 
-fn _load<T>(key: Option<&str>) -> anyhow::Result<Option<T>>
-where
-    T: serde::Serialize + for<'a> serde::Deserialize<'a>,
-{
-    let namespace = hash_type::<T>();
-    match esp_idf_svc::nvs::EspNvs::new(nvs(), &namespace, false) {
+fn _load_raw(namespace: &str, key: Option<&str>) -> anyhow::Result<Option<Vec<u8>>> {
+    match esp_idf_svc::nvs::EspNvs::new(nvs(), namespace, false) {
         Ok(storage) => {
             let key = key.unwrap_or("__default");
             match storage.blob_len(key)? {
                 Some(len) => {
                     let mut buffer = vec![0u8; len];
                     match storage.get_blob(key, &mut buffer) {
-                        Ok(Some(_)) => match bincode::deserialize::<T>(&buffer) {
-                            Ok(data) => Ok(Some(data)),
-                            Err(_) => Ok(None),
-                        },
+                        Ok(Some(_)) => Ok(Some(buffer)),
                         _ => Ok(None),
                     }
                 }
@@ -67,15 +60,33 @@ where
     }
 }
 
+fn _load<T>(key: Option<&str>) -> anyhow::Result<Option<T>>
+where
+    T: serde::Serialize + for<'a> serde::Deserialize<'a>,
+{
+    match _load_raw(&hash_type::<T>(), key)? {
+        Some(buffer) => match bincode::deserialize::<T>(&buffer) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) => {
+                // Most likely stored by a firmware with a different layout.
+                log::warn!(
+                    "Failed to decode {} from NVS key {}: {}",
+                    std::any::type_name::<T>(),
+                    key.unwrap_or("__default"),
+                    err
+                );
+                Ok(None)
+            }
+        },
+        None => Ok(None),
+    }
+}
+
 fn _remove<T>(key: Option<&str>) -> anyhow::Result<bool>
 where
     T: serde::Serialize + for<'a> serde::Deserialize<'a>,
 {
-    let mut storage = esp_idf_svc::nvs::EspNvs::new(
-        esp_idf_svc::nvs::EspNvsPartition::<esp_idf_svc::nvs::NvsDefault>::take().unwrap(),
-        &hash_type::<T>(),
-        true,
-    )?;
+    let mut storage = esp_idf_svc::nvs::EspNvs::new(nvs(), &hash_type::<T>(), true)?;
     Ok(storage.remove(key.unwrap_or("__default"))?)
 }
 
@@ -103,6 +114,12 @@ where
     _load(Some(key))
 }
 
+/// The undecoded blob stored for `T`, for reading layouts `T` no longer
+/// matches.
+pub fn load_raw<T>() -> anyhow::Result<Option<Vec<u8>>> {
+    _load_raw(&hash_type::<T>(), None)
+}
+
 #[allow(dead_code)]
 pub fn save_to<T>(data: T, key: &str) -> anyhow::Result<()>
 where
@@ -0,0 +1,395 @@
+//! Host-side stand-in for the BUPT campus portal.
+//!
+//! It answers the connectivity probes (`generate_204`, `hotspot-detect.html`
+//! and the Baidu home page at `/`) and the portal at `10.3.8.216` on a single
+//! address, so point all hosts at it, e.g. with `StdHttpClient::resolve` in
+//! the `bupt-portal` crate. Tests start it in-process with [`spawn`].
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+const PORTAL: &str = "http://10.3.8.216";
+const SESSION_COOKIE: &str = "PHPSESSID";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scenario {
+    /// Accepts the configured account, rejects anything else.
+    Normal,
+    /// Rejects every login attempt.
+    WrongPassword,
+    /// Never sets a session cookie, so logins without one are rejected.
+    NoCookie,
+    /// Like `NoCookie`, but logins are rejected because too many devices are
+    /// online, which the portal checks before the session.
+    TooManyDevices,
+    /// The probe redirects to a URL that redirects to itself forever.
+    RedirectLoop,
+    /// Like `Normal`, but every response is delayed.
+    Slow,
+    /// The probe always answers `204`.
+    Authenticated,
+}
+
+impl Scenario {
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "normal" => Scenario::Normal,
+            "wrong-password" => Scenario::WrongPassword,
+            "no-cookie" => Scenario::NoCookie,
+            "too-many-devices" => Scenario::TooManyDevices,
+            "redirect-loop" => Scenario::RedirectLoop,
+            "slow" => Scenario::Slow,
+            "authenticated" => Scenario::Authenticated,
+            _ => return None,
+        })
+    }
+}
+
+pub struct Options {
+    pub listen: String,
+    pub scenario: Scenario,
+    /// The only account accepted.
+    pub user: String,
+    pub pass: String,
+    /// How long every response is held back in the `Slow` scenario.
+    pub delay: Duration,
+    /// Prints every request and connection error, off so tests stay quiet.
+    pub log: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            listen: "127.0.0.1:8216".to_string(),
+            scenario: Scenario::Normal,
+            user: "2024000000".to_string(),
+            pass: "password".to_string(),
+            delay: Duration::from_secs(30),
+            log: false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    authenticated: bool,
+    sessions: Vec<String>,
+    next_session: u32,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn cookie(&self, name: &str) -> Option<&str> {
+        self.header("Cookie")?
+            .split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    fn form(&self) -> HashMap<String, String> {
+        self.body
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (decode(key), decode(value)))
+            .collect()
+    }
+}
+
+struct Response {
+    status: u16,
+    reason: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Response {
+    fn new(status: u16, reason: &'static str) -> Self {
+        Self {
+            status,
+            reason,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    fn redirect(location: String) -> Self {
+        let mut response = Self::new(302, "Found");
+        response.headers.push(("Location", location));
+        response
+    }
+
+    fn html(body: String) -> Self {
+        let mut response = Self::new(200, "OK");
+        response
+            .headers
+            .push(("Content-Type", "text/html; charset=utf-8".to_string()));
+        response.body = body;
+        response
+    }
+}
+
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| std::str::from_utf8(hex).ok());
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn page(title: &str, content: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{}</title></head>\
+         <body>{}</body></html>",
+        title, content
+    )
+}
+
+fn login_page(error: Option<&str>) -> String {
+    let error = error
+        .map(|message| format!("<div class=\"ui error message\">\n  {}\n</div>", message))
+        .unwrap_or_default();
+    page(
+        "北京邮电大学校园网认证",
+        &format!(
+            "{}<form method=\"post\" action=\"/login\">\
+             <input name=\"user\"><input name=\"pass\" type=\"password\">\
+             <button type=\"submit\">登录</button></form>",
+            error
+        ),
+    )
+}
+
+fn status_page(options: &Options) -> String {
+    page(
+        "北京邮电大学校园网认证",
+        &format!(
+            "<table>\
+             <tr><td>账号：</td><td>{}</td></tr>\
+             <tr><td>IP地址：</td><td>10.128.0.2</td></tr>\
+             <tr><td>已用流量：</td><td>1.23 GB</td></tr>\
+             <tr><td>已用时长：</td><td>42 分钟</td></tr>\
+             <tr><td>余额：</td><td>12.00 元</td></tr>\
+             </table><a href=\"/logout\">注销</a>",
+            options.user
+        ),
+    )
+}
+
+fn handle(request: &Request, options: &Options, state: &Mutex<State>) -> Response {
+    let mut state = state.lock().unwrap();
+    let path = request.path.split('?').next().unwrap_or("/");
+    match (request.method.as_str(), path) {
+        ("GET", "/generate_204") => {
+            if state.authenticated || options.scenario == Scenario::Authenticated {
+                Response::new(204, "No Content")
+            } else if options.scenario == Scenario::RedirectLoop {
+                Response::redirect(format!("{}/loop", PORTAL))
+            } else {
+                Response::redirect(format!("{}/index", PORTAL))
+            }
+        }
+        ("GET", "/hotspot-detect.html") | ("GET" | "HEAD", "/") => {
+            if state.authenticated || options.scenario == Scenario::Authenticated {
+                match path {
+                    "/" => Response::html(page("百度一下，你就知道", "百度一下")),
+                    _ => Response::html(page("Success", "Success")),
+                }
+            } else if options.scenario == Scenario::RedirectLoop {
+                Response::redirect(format!("{}/loop", PORTAL))
+            } else {
+                Response::redirect(format!("{}/index", PORTAL))
+            }
+        }
+        ("GET", "/loop") => Response::redirect(format!("{}/loop", PORTAL)),
+        ("GET", "/index") if state.authenticated => Response::html(status_page(options)),
+        ("GET", "/logout") => {
+            state.authenticated = false;
+            Response::html(page(
+                "注销成功",
+                "<div class=\"ui success message\">注销成功</div>",
+            ))
+        }
+        ("GET", "/index") => {
+            let mut response = Response::html(login_page(None));
+            if !matches!(
+                options.scenario,
+                Scenario::NoCookie | Scenario::TooManyDevices
+            ) {
+                state.next_session += 1;
+                let session = format!("emulator{:08}", state.next_session);
+                response.headers.push((
+                    "Set-Cookie",
+                    format!("{}={}; path=/; HttpOnly", SESSION_COOKIE, session),
+                ));
+                state.sessions.push(session);
+            }
+            response
+        }
+        ("POST", "/login") => {
+            let form = request.form();
+            let has_session = request
+                .cookie(SESSION_COOKIE)
+                .is_some_and(|session| state.sessions.iter().any(|s| s == session));
+            let error = if options.scenario == Scenario::TooManyDevices {
+                Some("在线终端数量已达上限")
+            } else if !has_session {
+                Some("会话已失效，请刷新页面后重试")
+            } else if options.scenario == Scenario::WrongPassword
+                || form.get("user") != Some(&options.user)
+                || form.get("pass") != Some(&options.pass)
+            {
+                Some("账号或密码错误")
+            } else {
+                None
+            };
+            match error {
+                Some(error) => Response::html(login_page(Some(error))),
+                None => {
+                    state.authenticated = true;
+                    Response::html(page(
+                        "认证成功",
+                        "<div class=\"ui success message\">认证成功</div>",
+                    ))
+                }
+            }
+        }
+        _ => Response::new(404, "Not Found"),
+    }
+}
+
+fn read_request(stream: &TcpStream) -> std::io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or("/").to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((key, value)) = header.split_once(':') {
+            headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let length = headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+fn serve(mut stream: TcpStream, options: &Options, state: &Mutex<State>) -> std::io::Result<()> {
+    let request = read_request(&stream)?;
+    let response = handle(&request, options, state);
+    if options.log {
+        println!(
+            "{} {} {} -> {}",
+            request.method,
+            request.header("Host").unwrap_or("-"),
+            request.path,
+            response.status
+        );
+    }
+
+    if options.scenario == Scenario::Slow {
+        thread::sleep(options.delay);
+    }
+
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, response.reason);
+    for (key, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", key, value));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    ));
+    stream.write_all(head.as_bytes())?;
+    if request.method != "HEAD" {
+        stream.write_all(response.body.as_bytes())?;
+    }
+    stream.flush()
+}
+
+/// Answers connections on `listener` until it fails, each on its own thread.
+pub fn run(listener: TcpListener, options: Options) -> std::io::Result<()> {
+    let options = Arc::new(options);
+    let state = Arc::new(Mutex::new(State::default()));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let options = Arc::clone(&options);
+        let state = Arc::clone(&state);
+        thread::spawn(move || {
+            if let Err(e) = serve(stream, &options, &state) {
+                if options.log {
+                    eprintln!("connection error: {}", e);
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Starts an emulator in the background, returns the address it listens on.
+/// Listen on port 0 to get a fresh instance per test.
+pub fn spawn(options: Options) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(&options.listen)?;
+    let addr = listener.local_addr()?;
+    thread::spawn(move || run(listener, options));
+    Ok(addr)
+}
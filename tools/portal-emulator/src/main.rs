@@ -0,0 +1,48 @@
+//! Runs the BUPT portal emulator, see the library for what it answers.
+
+use std::{env, net::TcpListener, process, time::Duration};
+
+use portal_emulator::{Options, Scenario};
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: portal-emulator [--listen ADDR] [--scenario NAME] [--user USER] [--pass PASS] \
+         [--delay SECONDS]\n\
+         scenarios: normal, wrong-password, no-cookie, too-many-devices, redirect-loop, \
+         slow, authenticated"
+    );
+    process::exit(2)
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        log: true,
+        ..Default::default()
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--listen" => options.listen = value,
+            "--scenario" => options.scenario = Scenario::parse(&value).unwrap_or_else(|| usage()),
+            "--user" => options.user = value,
+            "--pass" => options.pass = value,
+            "--delay" => {
+                options.delay = Duration::from_secs(value.parse().unwrap_or_else(|_| usage()))
+            }
+            _ => usage(),
+        }
+    }
+    options
+}
+
+fn main() -> std::io::Result<()> {
+    let options = parse_args();
+    let listener = TcpListener::bind(&options.listen)?;
+    println!(
+        "BUPT portal emulator listening on {} ({:?})",
+        listener.local_addr()?,
+        options.scenario
+    );
+    portal_emulator::run(listener, options)
+}